@@ -0,0 +1,461 @@
+// ALP, Adaptive Lossless floating-Point compression (Afroozeh et al., SIGMOD
+// 2024).
+//
+// Doubles that started life as decimals turn back into integers when
+// multiplied by 10^e and divided by 10^f for a suitable exponent `e` and
+// factor `f`. Each block of `VECTOR_SIZE` values samples a few of them to
+// pick the pair giving the smallest output, encodes every value as
+// round(x * 10^e / 10^f) and frame-of-reference bit-packs the integers.
+// Values that don't decode back to the same bits are stored raw as
+// exceptions.
+//
+// Blocks where that doesn't pay off, like high precision doubles, use ALP-RD
+// instead: the top bits of each value, which vary little, are dictionary
+// encoded and the remaining right bits are packed as is.
+//
+// Both work on f64 and f32 through `AlpFloat`, f32 using its own powers of
+// ten so the arithmetic round-trips in single precision.
+//
+// Layout: varint value count | blocks of min(VECTOR_SIZE, remaining) values
+//   ALP block:    0 | e | f | varint base | width | packed offsets
+//                 | exceptions
+//   ALP-RD block: 1 | right width | dict size | dict (u16 each)
+//                 | packed codes | packed right bits | exceptions
+//   exceptions:   varint count | positions (u16 each) | values
+
+use std::collections::HashMap;
+
+use crate::{
+    bitpacking::{bit_width, low_mask, max_bit_width, pack_into, packed_len, unpack},
+    error::CodingError,
+    float_xor::FloatBits,
+    varint::{encode_i64, encode_u64, take_bytes, take_i64, take_u64, take_u8},
+};
+
+pub const VECTOR_SIZE: usize = 1024;
+const SAMPLE_SIZE: usize = 32;
+
+const SCHEME_ALP: u8 = 0;
+const SCHEME_ALP_RD: u8 = 1;
+
+// Encoded integers stay well inside i64 so FOR offsets can't overflow
+const ENCODING_LIMIT: f64 = (1u64 << 62) as f64;
+
+// ALP-RD dictionary encodes at most the top 16 bits in 8 entries
+const RD_MAX_LEFT_BITS: u8 = 16;
+const RD_MAX_DICT_SIZE: usize = 8;
+// An ALP-RD exception costs its position and the raw left bits
+const RD_EXCEPTION_BITS: usize = 16 + 16;
+
+const F10: [f64; 19] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16,
+    1e17, 1e18,
+];
+const IF10: [f64; 19] = [
+    1e0, 1e-1, 1e-2, 1e-3, 1e-4, 1e-5, 1e-6, 1e-7, 1e-8, 1e-9, 1e-10, 1e-11, 1e-12, 1e-13, 1e-14,
+    1e-15, 1e-16, 1e-17, 1e-18,
+];
+
+const F10_F32: [f32; 11] = [1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+const IF10_F32: [f32; 11] = [
+    1e0, 1e-1, 1e-2, 1e-3, 1e-4, 1e-5, 1e-6, 1e-7, 1e-8, 1e-9, 1e-10,
+];
+
+// Float types ALP can scale to integers and back
+pub trait AlpFloat: FloatBits {
+    const MAX_EXPONENT: usize;
+
+    // round(self * 10^exponent / 10^factor), if that decodes back to the
+    // exact same bits
+    fn encode_value(self, exponent: usize, factor: usize) -> Option<i64>;
+    fn decode_value(encoded: i64, exponent: usize, factor: usize) -> Self;
+}
+
+macro_rules! impl_alp_float {
+    ($float:ty, $f10:expr, $if10:expr) => {
+        impl AlpFloat for $float {
+            const MAX_EXPONENT: usize = $f10.len() - 1;
+
+            fn encode_value(self, exponent: usize, factor: usize) -> Option<i64> {
+                let scaled = (self * $f10[exponent] * $if10[factor]).round();
+                if scaled.is_nan() || scaled.abs() as f64 >= ENCODING_LIMIT {
+                    return None;
+                }
+                let encoded = scaled as i64;
+                // Compare bits so -0.0 and NaN payloads are kept exactly
+                let decoded = Self::decode_value(encoded, exponent, factor);
+                (decoded.to_bits() == self.to_bits()).then_some(encoded)
+            }
+
+            fn decode_value(encoded: i64, exponent: usize, factor: usize) -> Self {
+                encoded as $float * $f10[factor] * $if10[exponent]
+            }
+        }
+    };
+}
+
+impl_alp_float!(f64, F10, IF10);
+impl_alp_float!(f32, F10_F32, IF10_F32);
+
+pub fn alp_encode<F: AlpFloat>(data: &[F]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    encode_u64(data.len() as u64, &mut encoded);
+    for block in data.chunks(VECTOR_SIZE) {
+        let step = (block.len() / SAMPLE_SIZE).max(1);
+        let sample: Vec<F> = block
+            .iter()
+            .step_by(step)
+            .take(SAMPLE_SIZE)
+            .copied()
+            .collect();
+        let (exponent, factor, alp_cost) = choose_exponent_factor(&sample);
+        let (right_width, rd_cost) = choose_right_width(&sample);
+        if alp_cost <= rd_cost {
+            encode_alp_block(block, exponent, factor, &mut encoded);
+        } else {
+            encode_rd_block(block, right_width, &mut encoded);
+        }
+    }
+    encoded
+}
+
+pub fn alp_decode<F: AlpFloat>(data: &[u8]) -> Result<Vec<F>, CodingError> {
+    let mut buff = data;
+    let len = take_u64(&mut buff)? as usize;
+    let mut decoded = Vec::new();
+    while decoded.len() < len {
+        let block_len = (len - decoded.len()).min(VECTOR_SIZE);
+        match take_u8(&mut buff)? {
+            SCHEME_ALP => decode_alp_block(&mut buff, block_len, &mut decoded)?,
+            SCHEME_ALP_RD => decode_rd_block(&mut buff, block_len, &mut decoded)?,
+            _ => return Err(CodingError::Malformed),
+        }
+    }
+    Ok(decoded)
+}
+
+// Tries every exponent and factor on the sample, returning the cheapest pair
+// and its estimated size in bits
+fn choose_exponent_factor<F: AlpFloat>(sample: &[F]) -> (usize, usize, usize) {
+    let mut best = (0, 0, usize::MAX);
+    for exponent in 0..=F::MAX_EXPONENT {
+        for factor in 0..=exponent {
+            let encoded: Vec<i64> = sample
+                .iter()
+                .filter_map(|x| x.encode_value(exponent, factor))
+                .collect();
+            let exceptions = sample.len() - encoded.len();
+            let width = match (encoded.iter().min(), encoded.iter().max()) {
+                (Some(min), Some(max)) => bit_width(max.wrapping_sub(*min) as u64),
+                _ => 0,
+            };
+            // An exception costs its position and the raw value
+            let exception_bits = 16 + F::BITS as usize;
+            let cost = sample.len() * width as usize + exceptions * exception_bits;
+            if cost < best.2 {
+                best = (exponent, factor, cost);
+            }
+        }
+    }
+    best
+}
+
+// Tries every split of the bits keeping the left part dictionary encodable,
+// returning the cheapest right width and its estimated size in bits
+fn choose_right_width<F: AlpFloat>(sample: &[F]) -> (u8, usize) {
+    let min_right_width = F::BITS as u8 - RD_MAX_LEFT_BITS;
+    let mut best = (min_right_width, usize::MAX);
+    for right_width in min_right_width..F::BITS as u8 {
+        let lefts: Vec<u64> = sample.iter().map(|x| x.to_u64() >> right_width).collect();
+        let dict = left_dictionary(&lefts);
+        let exceptions = lefts.iter().filter(|x| !dict.contains(x)).count();
+        let code_width = bit_width(dict.len().saturating_sub(1) as u64) as usize;
+        let cost = sample.len() * (right_width as usize + code_width)
+            + exceptions * RD_EXCEPTION_BITS
+            + dict.len() * RD_MAX_LEFT_BITS as usize;
+        if cost < best.1 {
+            best = (right_width, cost);
+        }
+    }
+    best
+}
+
+// The most frequent left parts, ties going to the smaller value
+fn left_dictionary(lefts: &[u64]) -> Vec<u64> {
+    let mut counts = HashMap::<u64, usize>::new();
+    for left in lefts {
+        *counts.entry(*left).or_default() += 1;
+    }
+    let mut counts: Vec<(u64, usize)> = counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+        .into_iter()
+        .take(RD_MAX_DICT_SIZE)
+        .map(|(left, _)| left)
+        .collect()
+}
+
+fn encode_alp_block<F: AlpFloat>(block: &[F], exponent: usize, factor: usize, out: &mut Vec<u8>) {
+    let encoded: Vec<Option<i64>> = block
+        .iter()
+        .map(|x| x.encode_value(exponent, factor))
+        .collect();
+    // Exceptions get an encodable value so they don't widen the offsets
+    let fill = encoded.iter().flatten().next().copied().unwrap_or(0);
+    let ints: Vec<i64> = encoded.iter().map(|x| x.unwrap_or(fill)).collect();
+    let base = ints.iter().min().copied().unwrap_or(0);
+    let offsets: Vec<u64> = ints.iter().map(|x| x.wrapping_sub(base) as u64).collect();
+    let width = max_bit_width(&offsets);
+
+    out.push(SCHEME_ALP);
+    out.push(exponent as u8);
+    out.push(factor as u8);
+    encode_i64(base, out);
+    out.push(width);
+    pack_into(&offsets, width, out);
+
+    let exceptions: Vec<(usize, u64)> = encoded
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.is_none())
+        .map(|(idx, _)| (idx, block[idx].to_u64()))
+        .collect();
+    write_exceptions(&exceptions, F::BITS as usize / 8, out);
+}
+
+fn decode_alp_block<F: AlpFloat>(
+    buff: &mut &[u8],
+    len: usize,
+    decoded: &mut Vec<F>,
+) -> Result<(), CodingError> {
+    let exponent = take_u8(buff)? as usize;
+    let factor = take_u8(buff)? as usize;
+    if exponent > F::MAX_EXPONENT || factor > exponent {
+        return Err(CodingError::Malformed);
+    }
+    let base = take_i64(buff)?;
+    let width = take_u8(buff)?;
+    if width > 64 {
+        return Err(CodingError::Malformed);
+    }
+    let packed = take_bytes(buff, packed_len(len, width))?;
+
+    let start = decoded.len();
+    for offset in unpack(packed, width, len) {
+        decoded.push(F::decode_value(
+            base.wrapping_add(offset as i64),
+            exponent,
+            factor,
+        ));
+    }
+    for (position, bits) in read_exceptions(buff, len, F::BITS as usize / 8)? {
+        decoded[start + position] = F::from_u64(bits);
+    }
+    Ok(())
+}
+
+fn encode_rd_block<F: AlpFloat>(block: &[F], right_width: u8, out: &mut Vec<u8>) {
+    let lefts: Vec<u64> = block.iter().map(|x| x.to_u64() >> right_width).collect();
+    let rights: Vec<u64> = block
+        .iter()
+        .map(|x| x.to_u64() & low_mask(right_width))
+        .collect();
+    let dict = left_dictionary(&lefts);
+
+    let mut codes = Vec::with_capacity(block.len());
+    let mut exceptions = Vec::new();
+    for (idx, left) in lefts.iter().enumerate() {
+        match dict.iter().position(|x| x == left) {
+            Some(code) => codes.push(code as u64),
+            None => {
+                codes.push(0);
+                exceptions.push((idx, *left));
+            }
+        }
+    }
+    let code_width = bit_width(dict.len() as u64 - 1);
+
+    out.push(SCHEME_ALP_RD);
+    out.push(right_width);
+    out.push(dict.len() as u8);
+    for left in &dict {
+        out.extend((*left as u16).to_le_bytes());
+    }
+    pack_into(&codes, code_width, out);
+    pack_into(&rights, right_width, out);
+    write_exceptions(&exceptions, 2, out);
+}
+
+fn decode_rd_block<F: AlpFloat>(
+    buff: &mut &[u8],
+    len: usize,
+    decoded: &mut Vec<F>,
+) -> Result<(), CodingError> {
+    let right_width = take_u8(buff)?;
+    let dict_size = take_u8(buff)? as usize;
+    let bits = F::BITS as u8;
+    if !(bits - RD_MAX_LEFT_BITS..bits).contains(&right_width)
+        || !(1..=RD_MAX_DICT_SIZE).contains(&dict_size)
+    {
+        return Err(CodingError::Malformed);
+    }
+    let dict: Vec<u64> = take_bytes(buff, 2 * dict_size)?
+        .chunks_exact(2)
+        .map(|x| u16::from_le_bytes([x[0], x[1]]) as u64)
+        .collect();
+    let code_width = bit_width(dict_size as u64 - 1);
+    let codes = unpack(
+        take_bytes(buff, packed_len(len, code_width))?,
+        code_width,
+        len,
+    );
+    let rights = unpack(
+        take_bytes(buff, packed_len(len, right_width))?,
+        right_width,
+        len,
+    );
+
+    let mut lefts = Vec::with_capacity(len);
+    for code in codes {
+        lefts.push(*dict.get(code as usize).ok_or(CodingError::Malformed)?);
+    }
+    for (position, left) in read_exceptions(buff, len, 2)? {
+        lefts[position] = left;
+    }
+    for (left, right) in lefts.iter().zip(rights) {
+        decoded.push(F::from_u64((left << right_width) | right));
+    }
+    Ok(())
+}
+
+fn write_exceptions(exceptions: &[(usize, u64)], value_bytes: usize, out: &mut Vec<u8>) {
+    encode_u64(exceptions.len() as u64, out);
+    for (position, _) in exceptions {
+        out.extend((*position as u16).to_le_bytes());
+    }
+    for (_, value) in exceptions {
+        out.extend(&value.to_le_bytes()[..value_bytes]);
+    }
+}
+
+fn read_exceptions(
+    buff: &mut &[u8],
+    len: usize,
+    value_bytes: usize,
+) -> Result<Vec<(usize, u64)>, CodingError> {
+    let count = take_u64(buff)? as usize;
+    if count > len {
+        return Err(CodingError::Malformed);
+    }
+    let positions = take_bytes(buff, 2 * count)?;
+    let values = take_bytes(buff, value_bytes * count)?;
+    let mut exceptions = Vec::with_capacity(count);
+    for (position, value) in positions
+        .chunks_exact(2)
+        .zip(values.chunks_exact(value_bytes))
+    {
+        let position = u16::from_le_bytes([position[0], position[1]]) as usize;
+        if position >= len {
+            return Err(CodingError::Malformed);
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..value_bytes].copy_from_slice(value);
+        exceptions.push((position, u64::from_le_bytes(bytes)));
+    }
+    Ok(exceptions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chimp::chimp_encode;
+
+    fn prices() -> Vec<f64> {
+        (0..5000u64)
+            .map(|x| (10_000 + (x * 7919) % 2000) as f64 / 100.0)
+            .collect()
+    }
+
+    fn assert_bits_eq<F: FloatBits>(decoded: &[F], data: &[F]) {
+        let decoded: Vec<u64> = decoded.iter().map(|x| x.to_u64()).collect();
+        let data: Vec<u64> = data.iter().map(|x| x.to_u64()).collect();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_encode_value() {
+        assert_eq!(0.1f64.encode_value(1, 0), Some(1));
+        assert_eq!(12.35f64.encode_value(2, 0), Some(1235));
+        assert_eq!(1200.0f64.encode_value(0, 2), Some(12));
+        assert_eq!(12.5f32.encode_value(1, 0), Some(125));
+        assert_eq!((-0.0f64).encode_value(0, 0), None);
+        assert_eq!(f64::NAN.encode_value(0, 0), None);
+        assert_eq!(f64::INFINITY.encode_value(0, 0), None);
+    }
+
+    #[test]
+    fn test_choose_exponent_factor() {
+        let (exponent, factor, cost) = choose_exponent_factor(&[1.25f64, 2.5, 10.75, 3.0]);
+        assert_eq!(exponent - factor, 2);
+        // 1075 - 125 needs 10 bits
+        assert_eq!(cost, 4 * 10);
+    }
+
+    #[test]
+    fn test_prices_round_trip() {
+        let data = prices();
+        let encoded = alp_encode(&data);
+        // Offsets below 2000 need 11 bits
+        assert!(encoded.len() < data.len() * 11 / 8 + 100);
+        assert!(encoded.len() < chimp_encode(&data).len() / 2);
+        assert_bits_eq(&alp_decode(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_exceptions_round_trip() {
+        let mut data = prices();
+        data[3] = f64::NAN;
+        data[700] = -0.0;
+        data[1500] = f64::NEG_INFINITY;
+        data[2048] = std::f64::consts::PI;
+        let encoded = alp_encode(&data);
+        assert_bits_eq(&alp_decode(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_f32_round_trip() {
+        let mut data: Vec<f32> = prices().iter().map(|x| *x as f32).collect();
+        data[10] = f32::NAN;
+        data[20] = -0.0;
+        let encoded = alp_encode(&data);
+        assert!(encoded.len() < data.len() * 2);
+        assert_bits_eq(&alp_decode::<f32>(&encoded).unwrap(), &data);
+
+        let data: Vec<f32> = (0..3000).map(|x| (x as f32 * 0.37).sin() + 2.0).collect();
+        let encoded = alp_encode(&data);
+        assert_eq!(encoded[2], SCHEME_ALP_RD);
+        assert_bits_eq(&alp_decode::<f32>(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_high_precision_uses_rd() {
+        let data: Vec<f64> = (0..3000).map(|x| (x as f64 * 0.37).sin() + 2.0).collect();
+        let encoded = alp_encode(&data);
+        // Right after the 2 byte count
+        assert_eq!(encoded[2], SCHEME_ALP_RD);
+        assert!(encoded.len() < data.len() * 8);
+        assert_bits_eq(&alp_decode(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_empty_and_truncated() {
+        assert!(alp_decode::<f64>(&alp_encode::<f64>(&[]))
+            .unwrap()
+            .is_empty());
+        let encoded = alp_encode(&prices());
+        assert!(matches!(
+            alp_decode::<f64>(&encoded[..encoded.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+    }
+}
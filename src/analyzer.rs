@@ -0,0 +1,431 @@
+// Picks a codec for a block of integers by trying them on a sample.
+//
+// The sample is a handful of short contiguous slices spread over the block,
+// contiguous so runs and deltas look the way they do in the full data. Cheap
+// statistics of the sample are kept for the report, then every candidate
+// encodes the sample and the smallest wins. Deltas wrap, so every codec
+// applies to any input.
+//
+// Codecs cut their input into blocks of at most `MAX_BLOCK_LEN` values,
+// stored one after the other. Every block starts with a varint value count:
+//   Raw: i64 values (LE)
+//   Rle: (zigzag varint value, varint run length) pairs
+//   Delta, DeltaDelta: zigzag varints of `delta_encode` / `delta_delta_encode`
+//   Dictionary: varint dict bytes length | dict bytes | width byte | packed codes
+//   For: zigzag varint minimum | width byte | packed offsets from the minimum
+// `auto_encode` prefixes the output with the codec's tag byte.
+
+use std::{collections::HashSet, num::Wrapping};
+
+use crate::{
+    bitpacking::{max_bit_width, pack_into, packed_len, unpack},
+    delta_encode::{delta_decode, delta_delta_decode, delta_delta_encode, delta_encode},
+    dict_encode::{decode_with_dict, dict_encode, EncodingDict},
+    error::CodingError,
+    rle::{rle_decode, rle_encode},
+    varint::{encode_i64, encode_u64, take_bytes, take_i64, take_u64, take_u8},
+};
+
+// Blocks up to this long are sampled whole
+pub const SAMPLE_LEN: usize = 1024;
+// Most values in one codec block, so a decoder never allocates for more
+// values than this whatever a block header claims
+pub const MAX_BLOCK_LEN: usize = 1 << 24;
+const SAMPLE_SLICES: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Raw = 0,
+    Rle = 1,
+    Delta = 2,
+    DeltaDelta = 3,
+    Dictionary = 4,
+    For = 5,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 6] = [
+        Codec::Raw,
+        Codec::Rle,
+        Codec::Delta,
+        Codec::DeltaDelta,
+        Codec::Dictionary,
+        Codec::For,
+    ];
+
+    fn from_tag(tag: u8) -> Result<Codec, CodingError> {
+        Codec::ALL
+            .get(tag as usize)
+            .copied()
+            .ok_or(CodingError::Malformed)
+    }
+}
+
+// Statistics of the sample
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SampleStats {
+    pub len: usize,
+    pub distinct: usize,
+    pub runs: usize,
+    pub min: i64,
+    pub max: i64,
+    // Smallest and largest difference between neighbours, 0 without any
+    pub min_delta: i128,
+    pub max_delta: i128,
+    // Non-decreasing
+    pub sorted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Analysis {
+    pub stats: SampleStats,
+    // Encoded size of the sample under each codec, in `Codec::ALL` order
+    pub costs: Vec<(Codec, usize)>,
+    // Smallest of `costs`, the earlier codec on ties
+    pub best: Codec,
+}
+
+pub fn analyze(data: &[i64]) -> Analysis {
+    let sample = sample(data);
+    let costs: Vec<(Codec, usize)> = Codec::ALL
+        .iter()
+        .map(|codec| (*codec, codec_encode(*codec, &sample).len()))
+        .collect();
+    let best = costs.iter().min_by_key(|x| x.1).unwrap().0;
+    Analysis {
+        stats: sample_stats(&sample),
+        costs,
+        best,
+    }
+}
+
+// Encodes the whole block with the codec that did best on the sample
+pub fn auto_encode(data: &[i64]) -> (Vec<u8>, Analysis) {
+    let analysis = analyze(data);
+    let mut encoded = vec![analysis.best as u8];
+    encoded.extend(codec_encode(analysis.best, data));
+    (encoded, analysis)
+}
+
+pub fn auto_decode(data: &[u8]) -> Result<Vec<i64>, CodingError> {
+    let mut buff = data;
+    let codec = Codec::from_tag(take_u8(&mut buff)?)?;
+    codec_decode(codec, buff)
+}
+
+pub fn codec_encode(codec: Codec, data: &[i64]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    if data.is_empty() {
+        encode_block(codec, data, &mut encoded);
+    }
+    for block in data.chunks(MAX_BLOCK_LEN) {
+        encode_block(codec, block, &mut encoded);
+    }
+    encoded
+}
+
+// Every block but the last is full
+pub fn codec_decode(codec: Codec, data: &[u8]) -> Result<Vec<i64>, CodingError> {
+    let mut buff = data;
+    let mut decoded = decode_block(codec, &mut buff)?;
+    let mut last_len = decoded.len();
+    while !buff.is_empty() {
+        if last_len != MAX_BLOCK_LEN {
+            return Err(CodingError::Malformed);
+        }
+        let block = decode_block(codec, &mut buff)?;
+        if block.is_empty() {
+            return Err(CodingError::Malformed);
+        }
+        last_len = block.len();
+        decoded.extend(block);
+    }
+    Ok(decoded)
+}
+
+fn encode_block(codec: Codec, data: &[i64], encoded: &mut Vec<u8>) {
+    encode_u64(data.len() as u64, encoded);
+    if data.is_empty() {
+        return;
+    }
+    match codec {
+        Codec::Raw => {
+            for value in data {
+                encoded.extend(value.to_le_bytes());
+            }
+        }
+        Codec::Rle => {
+            let values: Vec<isize> = data.iter().map(|x| *x as isize).collect();
+            for pair in rle_encode(&values) {
+                let pair = pair.as_bytes();
+                encode_i64(pair[0] as i64, encoded);
+                encode_u64(pair[1] as u64, encoded);
+            }
+        }
+        Codec::Delta => {
+            for delta in delta_encode(&wrapping(data)) {
+                encode_i64(delta.0, encoded);
+            }
+        }
+        Codec::DeltaDelta => {
+            // Needs two values to start from
+            let deltas = if data.len() < 2 {
+                wrapping(data)
+            } else {
+                delta_delta_encode(&wrapping(data))
+            };
+            for delta in deltas {
+                encode_i64(delta.0, encoded);
+            }
+        }
+        Codec::Dictionary => {
+            let (codes, dict) = dict_encode(data);
+            let dict_bytes = dict.to_bytes();
+            encode_u64(dict_bytes.len() as u64, encoded);
+            encoded.extend(dict_bytes);
+            write_packed(&codes, encoded);
+        }
+        Codec::For => {
+            let min = *data.iter().min().unwrap();
+            encode_i64(min, encoded);
+            let offsets: Vec<u64> = data.iter().map(|x| x.wrapping_sub(min) as u64).collect();
+            write_packed(&offsets, encoded);
+        }
+    }
+}
+
+fn decode_block(codec: Codec, buff: &mut &[u8]) -> Result<Vec<i64>, CodingError> {
+    let len = take_u64(buff)? as usize;
+    if len > MAX_BLOCK_LEN {
+        return Err(CodingError::Malformed);
+    }
+    let decoded = if len == 0 {
+        Vec::new()
+    } else {
+        match codec {
+            Codec::Raw => take_bytes(buff, len.saturating_mul(8))?
+                .chunks_exact(8)
+                .map(|x| i64::from_le_bytes(x.try_into().unwrap()))
+                .collect(),
+            Codec::Rle => {
+                let mut pairs = Vec::new();
+                let mut total = 0;
+                while total < len {
+                    let value = take_i64(buff)?;
+                    let run = take_u64(buff)? as usize;
+                    if run == 0 || run > len - total {
+                        return Err(CodingError::Malformed);
+                    }
+                    total += run;
+                    pairs.extend([value as u64, run as u64]);
+                }
+                rle_decode(&pairs).into_iter().map(|x| x as i64).collect()
+            }
+            Codec::Delta => {
+                let deltas = take_wrapping(buff, len)?;
+                delta_decode(&deltas).into_iter().map(|x| x.0).collect()
+            }
+            Codec::DeltaDelta => {
+                let deltas = take_wrapping(buff, len)?;
+                if len < 2 {
+                    deltas.into_iter().map(|x| x.0).collect()
+                } else {
+                    delta_delta_decode(&deltas)
+                        .into_iter()
+                        .map(|x| x.0)
+                        .collect()
+                }
+            }
+            Codec::Dictionary => {
+                let dict_len = take_u64(buff)? as usize;
+                let dict = EncodingDict::<i64>::from_bytes(take_bytes(buff, dict_len)?)?;
+                decode_with_dict(&read_packed(buff, len)?, &dict)?
+            }
+            Codec::For => {
+                let min = take_i64(buff)?;
+                read_packed(buff, len)?
+                    .into_iter()
+                    .map(|x| min.wrapping_add(x as i64))
+                    .collect()
+            }
+        }
+    };
+    Ok(decoded)
+}
+
+// Evenly spaced contiguous slices adding up to `SAMPLE_LEN` values
+fn sample(data: &[i64]) -> Vec<i64> {
+    if data.len() <= SAMPLE_LEN {
+        return data.to_vec();
+    }
+    let slice_len = SAMPLE_LEN / SAMPLE_SLICES;
+    let step = (data.len() - slice_len) / (SAMPLE_SLICES - 1);
+    (0..SAMPLE_SLICES)
+        .flat_map(|idx| &data[idx * step..idx * step + slice_len])
+        .copied()
+        .collect()
+}
+
+fn sample_stats(sample: &[i64]) -> SampleStats {
+    let deltas: Vec<i128> = sample
+        .windows(2)
+        .map(|x| x[1] as i128 - x[0] as i128)
+        .collect();
+    SampleStats {
+        len: sample.len(),
+        distinct: sample.iter().collect::<HashSet<_>>().len(),
+        runs: deltas.iter().filter(|x| **x != 0).count() + !sample.is_empty() as usize,
+        min: sample.iter().copied().min().unwrap_or(0),
+        max: sample.iter().copied().max().unwrap_or(0),
+        min_delta: deltas.iter().copied().min().unwrap_or(0),
+        max_delta: deltas.iter().copied().max().unwrap_or(0),
+        sorted: deltas.iter().all(|x| *x >= 0),
+    }
+}
+
+fn wrapping(data: &[i64]) -> Vec<Wrapping<i64>> {
+    data.iter().map(|x| Wrapping(*x)).collect()
+}
+
+fn take_wrapping(data: &mut &[u8], len: usize) -> Result<Vec<Wrapping<i64>>, CodingError> {
+    (0..len).map(|_| take_i64(data).map(Wrapping)).collect()
+}
+
+fn write_packed(values: &[u64], out: &mut Vec<u8>) {
+    let width = max_bit_width(values);
+    out.push(width);
+    pack_into(values, width, out);
+}
+
+fn read_packed(data: &mut &[u8], len: usize) -> Result<Vec<u64>, CodingError> {
+    let width = take_u8(data)?;
+    if width > 64 {
+        return Err(CodingError::Malformed);
+    }
+    Ok(unpack(
+        take_bytes(data, packed_len(len, width))?,
+        width,
+        len,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cost(analysis: &Analysis, codec: Codec) -> usize {
+        analysis.costs.iter().find(|x| x.0 == codec).unwrap().1
+    }
+
+    #[test]
+    fn test_round_trip_every_codec() {
+        let inputs: Vec<Vec<i64>> = vec![
+            (0..1000).map(|x| x * x % 97 - 40).collect(),
+            vec![i64::MIN, i64::MAX, 0, -1, i64::MAX],
+            vec![42],
+            Vec::new(),
+        ];
+        for data in &inputs {
+            for codec in Codec::ALL {
+                let encoded = codec_encode(codec, data);
+                assert_eq!(&codec_decode(codec, &encoded).unwrap(), data, "{codec:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_picks_suitable_codec() {
+        let runs: Vec<i64> = (0..5000).map(|x| x / 1000).collect();
+        assert_eq!(analyze(&runs).best, Codec::Rle);
+
+        // Regular timestamps
+        let timestamps: Vec<i64> = (0..5000).map(|x| 1_700_000_000_000 + x * 10_000).collect();
+        assert_eq!(analyze(&timestamps).best, Codec::DeltaDelta);
+
+        // Random walk
+        let mut state = 1u64;
+        let mut value = 1_000_000i64;
+        let walk: Vec<i64> = (0..5000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                value += (state >> 60) as i64 - 8;
+                value
+            })
+            .collect();
+        assert_eq!(analyze(&walk).best, Codec::Delta);
+
+        let labels = [-5_000_000_000i64, 17, 9_000_000_000_000, 3];
+        let categorical: Vec<i64> = (0..5000).map(|x| labels[x * 7 % 13 % 4]).collect();
+        assert_eq!(analyze(&categorical).best, Codec::Dictionary);
+
+        let readings: Vec<i64> = (0..5000).map(|x| 5_000_000 + (x * 7919) % 200).collect();
+        assert_eq!(analyze(&readings).best, Codec::For);
+    }
+
+    #[test]
+    fn test_stats() {
+        let data = [5, 5, 7, 7, 7, 3];
+        let stats = analyze(&data).stats;
+        assert_eq!(stats.len, 6);
+        assert_eq!(stats.distinct, 3);
+        assert_eq!(stats.runs, 3);
+        assert_eq!((stats.min, stats.max), (3, 7));
+        assert_eq!((stats.min_delta, stats.max_delta), (-4, 2));
+        assert!(!stats.sorted);
+
+        let long: Vec<i64> = (0..100_000).collect();
+        let analysis = analyze(&long);
+        assert_eq!(analysis.stats.len, SAMPLE_LEN);
+        assert!(analysis.stats.sorted);
+        assert_eq!(cost(&analysis, Codec::Raw), 2 + 8 * SAMPLE_LEN);
+    }
+
+    #[test]
+    fn test_auto_round_trip() {
+        let data: Vec<i64> = (0..20_000).map(|x| (x / 300) * 3 - 100).collect();
+        let (encoded, analysis) = auto_encode(&data);
+        assert_eq!(encoded[0], analysis.best as u8);
+        assert!(encoded.len() < data.len());
+        assert_eq!(auto_decode(&encoded).unwrap(), data);
+        assert!(matches!(auto_decode(&[9, 0]), Err(CodingError::Malformed)));
+        assert!(matches!(
+            auto_decode(&encoded[..encoded.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_rle_header_too_long() {
+        // A single run claiming more values than any block holds
+        let mut crafted = vec![Codec::Rle as u8];
+        let len = MAX_BLOCK_LEN as u64 + 1;
+        encode_u64(len, &mut crafted);
+        encode_i64(7, &mut crafted);
+        encode_u64(len, &mut crafted);
+        assert!(matches!(auto_decode(&crafted), Err(CodingError::Malformed)));
+    }
+
+    #[test]
+    fn test_multiple_blocks() {
+        let data: Vec<i64> = (0..MAX_BLOCK_LEN as i64 + 3).map(|x| x / 4096).collect();
+        let encoded = codec_encode(Codec::Rle, &data);
+        assert_eq!(codec_decode(Codec::Rle, &encoded).unwrap(), data);
+        let (encoded, analysis) = auto_encode(&data);
+        assert_eq!(analysis.best, Codec::Rle);
+        assert_eq!(auto_decode(&encoded).unwrap(), data);
+
+        // Only the last block may be short, and only the first empty
+        let mut bad = codec_encode(Codec::Raw, &[1, 2]);
+        bad.extend(codec_encode(Codec::Raw, &[3]));
+        assert!(matches!(
+            codec_decode(Codec::Raw, &bad),
+            Err(CodingError::Malformed)
+        ));
+        let mut bad = codec_encode(Codec::Rle, &vec![0; MAX_BLOCK_LEN]);
+        bad.push(0);
+        assert!(matches!(
+            codec_decode(Codec::Rle, &bad),
+            Err(CodingError::Malformed)
+        ));
+    }
+}
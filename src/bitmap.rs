@@ -0,0 +1,125 @@
+// Fixed length selection bitmap, one bit per row, as produced by predicate
+// scans over encoded columns.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    len: usize,
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    // All bits clear
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set(&mut self, idx: usize) {
+        assert!(idx < self.len, "bit {idx} out of range for {}", self.len);
+        self.words[idx / 64] |= 1 << (idx % 64);
+    }
+
+    // False past the end
+    pub fn get(&self, idx: usize) -> bool {
+        idx < self.len && self.words[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|x| x.count_ones() as usize).sum()
+    }
+
+    // Indices of the set bits in increasing order
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(idx, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(idx * 64 + bit)
+            })
+        })
+    }
+
+    pub fn and(&self, other: &Bitmap) -> Bitmap {
+        self.combine(other, |a, b| a & b)
+    }
+
+    pub fn or(&self, other: &Bitmap) -> Bitmap {
+        self.combine(other, |a, b| a | b)
+    }
+
+    pub fn not(&self) -> Bitmap {
+        let mut words: Vec<u64> = self.words.iter().map(|x| !x).collect();
+        // Keep the bits past `len` clear
+        if !self.len.is_multiple_of(64) {
+            *words.last_mut().unwrap() &= (1 << (self.len % 64)) - 1;
+        }
+        Bitmap {
+            len: self.len,
+            words,
+        }
+    }
+
+    fn combine(&self, other: &Bitmap, op: impl Fn(u64, u64) -> u64) -> Bitmap {
+        assert_eq!(self.len, other.len, "bitmap lengths differ");
+        Bitmap {
+            len: self.len,
+            words: self
+                .words
+                .iter()
+                .zip(&other.words)
+                .map(|(a, b)| op(*a, *b))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_get() {
+        let mut bitmap = Bitmap::new(130);
+        for idx in [0, 63, 64, 129] {
+            bitmap.set(idx);
+        }
+        assert!(bitmap.get(63) && bitmap.get(64) && bitmap.get(129));
+        assert!(!bitmap.get(1) && !bitmap.get(130));
+        assert_eq!(bitmap.count_ones(), 4);
+        assert_eq!(bitmap.ones().collect::<Vec<usize>>(), vec![0, 63, 64, 129]);
+    }
+
+    #[test]
+    fn test_combine() {
+        let mut a = Bitmap::new(70);
+        let mut b = Bitmap::new(70);
+        a.set(1);
+        a.set(65);
+        b.set(65);
+        b.set(69);
+        assert_eq!(a.and(&b).ones().collect::<Vec<usize>>(), vec![65]);
+        assert_eq!(a.or(&b).ones().collect::<Vec<usize>>(), vec![1, 65, 69]);
+        assert_eq!(a.not().count_ones(), 68);
+        assert!(!a.not().get(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_out_of_range() {
+        Bitmap::new(10).set(10);
+    }
+}
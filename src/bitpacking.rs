@@ -0,0 +1,323 @@
+// Fixed-width bit-packing: every value is stored in exactly `width` bits,
+// LSB first, with no per-value headers.
+//
+// Full blocks of `BLOCK_SIZE` values go through kernels specialized on the
+// width at compile time so the inner loop is fully unrolled, a block packs
+// to exactly `2 * width` little-endian words. A trailing partial block uses
+// the generic loop, which produces the same bit layout, so the output for
+// `n` values is always `packed_len(n, width)` bytes.
+//
+// `BitWriter` and `BitReader` use the same layout for streams where each
+// field has its own width, like the control bits of the float codecs.
+
+use crate::error::CodingError;
+
+pub const BLOCK_SIZE: usize = 128;
+
+// Expands to a match calling `kernel::<W>(args)` for the runtime `width`
+macro_rules! specialize {
+    ($width:expr, $kernel:ident $args:tt) => {
+        specialize!(@arms $width, $kernel $args;
+            1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+            17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+            33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48
+            49 50 51 52 53 54 55 56 57 58 59 60 61 62 63 64)
+    };
+    (@arms $width:expr, $kernel:ident $args:tt; $($w:literal)*) => {
+        match $width {
+            $($w => $kernel::<$w> $args,)*
+            _ => unreachable!(),
+        }
+    };
+}
+
+pub fn pack(values: &[u64], width: u8) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(packed_len(values.len(), width));
+    pack_into(values, width, &mut packed);
+    packed
+}
+
+// Same as `pack` but appends to an existing buffer
+pub fn pack_into(values: &[u64], width: u8, out: &mut Vec<u8>) {
+    assert!(width <= 64, "bit width {} is larger than 64", width);
+    if width == 0 {
+        return;
+    }
+    let mut blocks = values.chunks_exact(BLOCK_SIZE);
+    for block in blocks.by_ref() {
+        let start = out.len();
+        out.resize(start + block_len(width), 0);
+        specialize!(width, pack_block(block, &mut out[start..]));
+    }
+    pack_generic(blocks.remainder(), width, out);
+}
+
+// `data` must hold at least `packed_len(len, width)` bytes
+pub fn unpack(data: &[u8], width: u8, len: usize) -> Vec<u64> {
+    assert!(width <= 64, "bit width {} is larger than 64", width);
+    let mut unpacked = Vec::with_capacity(len);
+    if width == 0 {
+        unpacked.resize(len, 0);
+        return unpacked;
+    }
+    let full_blocks = len / BLOCK_SIZE;
+    let mut buff = data;
+    for _ in 0..full_blocks {
+        specialize!(
+            width,
+            unpack_block(&buff[..block_len(width)], &mut unpacked)
+        );
+        buff = &buff[block_len(width)..];
+    }
+    unpack_generic(buff, width, len - full_blocks * BLOCK_SIZE, &mut unpacked);
+    unpacked
+}
+
+// Random access to the `index`th value without unpacking the rest
+pub fn get(data: &[u8], width: u8, index: usize) -> u64 {
+    if width == 0 {
+        return 0;
+    }
+    let bit = index * width as usize;
+    let shift = bit % 8;
+    let start = bit / 8;
+    let end = start + (shift + width as usize).div_ceil(8);
+    let mut bytes = [0u8; 16];
+    bytes[..end - start].copy_from_slice(&data[start..end]);
+    (u128::from_le_bytes(bytes) >> shift) as u64 & low_mask(width)
+}
+
+pub fn packed_len(len: usize, width: u8) -> usize {
+    (len * width as usize).div_ceil(8)
+}
+
+// Number of bits needed to represent `value`
+pub fn bit_width(value: u64) -> u8 {
+    (64 - value.leading_zeros()) as u8
+}
+
+// Width needed to pack every value in `values`
+pub fn max_bit_width(values: &[u64]) -> u8 {
+    bit_width(values.iter().fold(0, |acc, x| acc | x))
+}
+
+pub fn low_mask(width: u8) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+fn block_len(width: u8) -> usize {
+    BLOCK_SIZE * width as usize / 8
+}
+
+fn pack_block<const W: u32>(block: &[u64], out: &mut [u8]) {
+    let mask = low_mask(W as u8);
+    let mut words = out.chunks_exact_mut(8);
+    let mut word: u64 = 0;
+    let mut filled = 0;
+    for value in &block[..BLOCK_SIZE] {
+        let value = value & mask;
+        word |= value << filled;
+        filled += W;
+        if filled >= 64 {
+            words.next().unwrap().copy_from_slice(&word.to_le_bytes());
+            filled -= 64;
+            // Carry the bits of `value` that didn't fit into the next word
+            word = if filled > 0 { value >> (W - filled) } else { 0 };
+        }
+    }
+}
+
+fn unpack_block<const W: u32>(data: &[u8], out: &mut Vec<u64>) {
+    let mask = low_mask(W as u8);
+    let mut words = [0u64; 2 * 64];
+    for (word, bytes) in words.iter_mut().zip(data.chunks_exact(8)) {
+        *word = u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+    let mut idx = 0;
+    let mut bit = 0;
+    for _ in 0..BLOCK_SIZE {
+        let mut value = words[idx] >> bit;
+        if bit + W > 64 {
+            value |= words[idx + 1] << (64 - bit);
+        }
+        out.push(value & mask);
+        bit += W;
+        if bit >= 64 {
+            bit -= 64;
+            idx += 1;
+        }
+    }
+}
+
+fn pack_generic(values: &[u64], width: u8, out: &mut Vec<u8>) {
+    let mask = low_mask(width);
+    let mut acc: u128 = 0;
+    let mut filled = 0;
+    for value in values {
+        acc |= ((value & mask) as u128) << filled;
+        filled += width as u32;
+        while filled >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            filled -= 8;
+        }
+    }
+    if filled > 0 {
+        out.push(acc as u8);
+    }
+}
+
+fn unpack_generic(data: &[u8], width: u8, len: usize, out: &mut Vec<u64>) {
+    let mask = low_mask(width);
+    let mut acc: u128 = 0;
+    let mut filled = 0;
+    let mut bytes = data.iter();
+    for _ in 0..len {
+        while filled < width as u32 {
+            acc |= (*bytes.next().unwrap() as u128) << filled;
+            filled += 8;
+        }
+        out.push(acc as u64 & mask);
+        acc >>= width;
+        filled -= width as u32;
+    }
+}
+
+#[derive(Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u128,
+    filled: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Appends the low `bits` bits of `value`
+    pub fn write(&mut self, value: u64, bits: u32) {
+        self.acc |= ((value & low_mask(bits as u8)) as u128) << self.filled;
+        self.filled += bits;
+        while self.filled >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.filled -= 8;
+        }
+    }
+
+    // Pads the last byte with zeros
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.filled > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn read(&mut self, bits: u32) -> Result<u64, CodingError> {
+        if bits == 0 {
+            return Ok(0);
+        }
+        if self.pos + bits as usize > self.data.len() * 8 {
+            return Err(CodingError::Truncated);
+        }
+        let start = self.pos / 8;
+        let end = (self.pos + bits as usize).div_ceil(8);
+        let mut bytes = [0u8; 16];
+        bytes[..end - start].copy_from_slice(&self.data[start..end]);
+        let value = (u128::from_le_bytes(bytes) >> (self.pos % 8)) as u64 & low_mask(bits as u8);
+        self.pos += bits as usize;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_values(len: usize, width: u8) -> Vec<u64> {
+        (0..len as u64)
+            .map(|x| x.wrapping_mul(0x9E3779B97F4A7C15) & low_mask(width))
+            .collect()
+    }
+
+    #[test]
+    fn test_pack_small() {
+        let packed = pack(&[1, 2, 3, 0], 2);
+        assert_eq!(packed, vec![0b00111001]);
+    }
+
+    #[test]
+    fn test_kernels_match_generic() {
+        for width in 1..=64 {
+            let values = test_values(BLOCK_SIZE, width);
+            let mut generic = Vec::new();
+            pack_generic(&values, width, &mut generic);
+            assert_eq!(pack(&values, width), generic, "width {}", width);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_widths() {
+        for width in 0..=64 {
+            for len in [0, 1, 127, 128, 129, 300, 512] {
+                let values = test_values(len, width);
+                let packed = pack(&values, width);
+                assert_eq!(packed.len(), packed_len(len, width));
+                assert_eq!(unpack(&packed, width, len), values, "width {}", width);
+            }
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        for width in [1, 7, 8, 13, 63, 64] {
+            let values = test_values(300, width);
+            let packed = pack(&values, width);
+            for (idx, value) in values.iter().enumerate() {
+                assert_eq!(get(&packed, width, idx), *value, "width {}", width);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bit_stream_round_trip() {
+        let fields: Vec<(u64, u32)> = (0..200u64)
+            .map(|x| (x.wrapping_mul(0x9E3779B97F4A7C15), (x % 65) as u32))
+            .collect();
+        let mut writer = BitWriter::new();
+        for (value, bits) in &fields {
+            writer.write(*value, *bits);
+        }
+        let bytes = writer.finish();
+        let mut reader = BitReader::new(&bytes);
+        for (value, bits) in &fields {
+            assert_eq!(reader.read(*bits).unwrap(), value & low_mask(*bits as u8));
+        }
+        assert!(matches!(reader.read(64), Err(CodingError::Truncated)));
+    }
+
+    #[test]
+    fn test_bit_width() {
+        assert_eq!(bit_width(0), 0);
+        assert_eq!(bit_width(1), 1);
+        assert_eq!(bit_width(255), 8);
+        assert_eq!(bit_width(u64::MAX), 64);
+        assert_eq!(max_bit_width(&[1, 4, 2]), 3);
+    }
+}
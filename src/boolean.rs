@@ -0,0 +1,256 @@
+// Boolean columns such as status flags. Values are cut into blocks of
+// `BLOCK_LEN` and each block is stored whichever way is smaller: packed one
+// bit per value, or as the lengths of its alternating runs packed with
+// simple8b, which suits flags that stay up or down for long stretches.
+// `count_true` works on the blocks as stored, without expanding them.
+//
+// Packed block: tag 0 | one bit per value, u64 words (LE)
+// Run block: tag 1 | first value byte | varint word count | simple8b words (LE)
+// Layout: varint value count | blocks
+
+use crate::{
+    error::CodingError,
+    simple8b::{simple8b_decode, simple8b_encode},
+    varint::{encode_u64, encoded_len, take_bytes, take_u64, take_u8},
+};
+
+pub const BLOCK_LEN: usize = 4096;
+
+const PACKED: u8 = 0;
+const RUNS: u8 = 1;
+
+pub fn boolean_encode(data: &[bool]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    encode_u64(data.len() as u64, &mut encoded);
+    for block in data.chunks(BLOCK_LEN) {
+        let words = simple8b_encode(&run_lengths(block));
+        let runs_len = 1 + encoded_len(words.len() as u64) + 8 * words.len();
+        if runs_len < 8 * block.len().div_ceil(64) {
+            encoded.push(RUNS);
+            encoded.push(block[0] as u8);
+            encode_u64(words.len() as u64, &mut encoded);
+            for word in words {
+                encoded.extend(word.to_le_bytes());
+            }
+        } else {
+            encoded.push(PACKED);
+            for bits in block.chunks(64) {
+                let word = bits
+                    .iter()
+                    .enumerate()
+                    .fold(0u64, |word, (idx, bit)| word | ((*bit as u64) << idx));
+                encoded.extend(word.to_le_bytes());
+            }
+        }
+    }
+    encoded
+}
+
+pub fn boolean_decode(data: &[u8]) -> Result<Vec<bool>, CodingError> {
+    let mut decoded = Vec::new();
+    for block in read_blocks(data)? {
+        match block {
+            Block::Packed(words, len) => {
+                decoded.extend((0..len).map(|idx| words[idx / 64] & (1 << (idx % 64)) != 0));
+            }
+            Block::Runs(first, runs) => {
+                for (idx, run) in runs.iter().enumerate() {
+                    let value = first ^ !idx.is_multiple_of(2);
+                    decoded.extend(std::iter::repeat_n(value, *run as usize));
+                }
+            }
+        }
+    }
+    Ok(decoded)
+}
+
+// Number of true values, from popcounts and run lengths
+pub fn count_true(data: &[u8]) -> Result<usize, CodingError> {
+    let mut count = 0;
+    for block in read_blocks(data)? {
+        count += match block {
+            Block::Packed(words, _) => words.iter().map(|x| x.count_ones() as usize).sum::<usize>(),
+            // Runs alternate, starting with `first`
+            Block::Runs(first, runs) => runs
+                .iter()
+                .skip(!first as usize)
+                .step_by(2)
+                .map(|x| *x as usize)
+                .sum::<usize>(),
+        };
+    }
+    Ok(count)
+}
+
+enum Block {
+    // Words and the number of values in them
+    Packed(Vec<u64>, usize),
+    // First value and the run lengths
+    Runs(bool, Vec<u64>),
+}
+
+fn run_lengths(block: &[bool]) -> Vec<u64> {
+    let mut runs = vec![0u64];
+    let mut current = block[0];
+    for value in block {
+        if *value != current {
+            runs.push(0);
+            current = *value;
+        }
+        *runs.last_mut().unwrap() += 1;
+    }
+    runs
+}
+
+fn read_blocks(data: &[u8]) -> Result<Vec<Block>, CodingError> {
+    let mut buff = data;
+    let len = take_u64(&mut buff)? as usize;
+    let mut blocks = Vec::new();
+    let mut remaining = len;
+    while remaining > 0 {
+        let block_len = remaining.min(BLOCK_LEN);
+        remaining -= block_len;
+        let block = match take_u8(&mut buff)? {
+            PACKED => {
+                let words: Vec<u64> = take_bytes(&mut buff, 8 * block_len.div_ceil(64))?
+                    .chunks_exact(8)
+                    .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                // Bits past the end must be clear
+                if !block_len.is_multiple_of(64) && words.last().unwrap() >> (block_len % 64) != 0 {
+                    return Err(CodingError::Malformed);
+                }
+                Block::Packed(words, block_len)
+            }
+            RUNS => {
+                let first = match take_u8(&mut buff)? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(CodingError::Malformed),
+                };
+                let count = take_u64(&mut buff)? as usize;
+                let words: Vec<u64> = take_bytes(&mut buff, count.saturating_mul(8))?
+                    .chunks_exact(8)
+                    .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                // simple8b never writes the zero width selectors
+                if words.iter().any(|x| x >> 60 < 2) {
+                    return Err(CodingError::Malformed);
+                }
+                let runs = simple8b_decode(&words);
+                let total = runs
+                    .iter()
+                    .try_fold(0u64, |acc, x| acc.checked_add(*x))
+                    .ok_or(CodingError::Malformed)?;
+                if runs.contains(&0) || total != block_len as u64 {
+                    return Err(CodingError::Malformed);
+                }
+                Block::Runs(first, runs)
+            }
+            _ => return Err(CodingError::Malformed),
+        };
+        blocks.push(block);
+    }
+    if !buff.is_empty() {
+        return Err(CodingError::Malformed);
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Flag that flips every `period` values
+    fn square_wave(len: usize, period: usize) -> Vec<bool> {
+        (0..len).map(|x| (x / period).is_multiple_of(2)).collect()
+    }
+
+    fn noisy(len: usize) -> Vec<bool> {
+        let mut state = 7u64;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                state >> 63 == 1
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for data in [
+            square_wave(10_000, 500),
+            noisy(10_000),
+            noisy(100),
+            vec![true],
+            vec![false; 5000],
+            Vec::new(),
+        ] {
+            let encoded = boolean_encode(&data);
+            assert_eq!(boolean_decode(&encoded).unwrap(), data);
+            assert_eq!(
+                count_true(&encoded).unwrap(),
+                data.iter().filter(|x| **x).count()
+            );
+        }
+    }
+
+    #[test]
+    fn test_chooses_encoding() {
+        // A couple of runs per block
+        let encoded = boolean_encode(&square_wave(BLOCK_LEN, 1000));
+        assert_eq!(encoded[2], RUNS);
+        assert!(encoded.len() < 20);
+
+        let encoded = boolean_encode(&noisy(BLOCK_LEN));
+        assert_eq!(encoded[2], PACKED);
+        assert_eq!(encoded.len(), 2 + 1 + BLOCK_LEN / 8);
+    }
+
+    #[test]
+    fn test_mixed_blocks() {
+        let mut data = square_wave(BLOCK_LEN, 2048);
+        data.extend(noisy(BLOCK_LEN));
+        data.extend(square_wave(1000, 300));
+        let encoded = boolean_encode(&data);
+        assert_eq!(boolean_decode(&encoded).unwrap(), data);
+        assert_eq!(
+            count_true(&encoded).unwrap(),
+            data.iter().filter(|x| **x).count()
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let encoded = boolean_encode(&square_wave(3000, 100));
+        assert!(matches!(
+            boolean_decode(&encoded[..encoded.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+        // Run lengths add up to 3 for a block of 4
+        let mut bad = vec![4, RUNS, 1, 1];
+        bad.extend(
+            simple8b_encode(&[1, 2])
+                .into_iter()
+                .flat_map(u64::to_le_bytes),
+        );
+        assert!(matches!(boolean_decode(&bad), Err(CodingError::Malformed)));
+        // Run lengths that overflow a u64 when added up
+        let mut bad = vec![4, RUNS, 1, 32];
+        bad.extend(
+            simple8b_encode(&[(1 << 60) - 1; 32])
+                .into_iter()
+                .flat_map(u64::to_le_bytes),
+        );
+        assert!(matches!(boolean_decode(&bad), Err(CodingError::Malformed)));
+        assert!(matches!(count_true(&bad), Err(CodingError::Malformed)));
+        // Bit set past the end of a packed block
+        let mut bad = vec![3, PACKED];
+        bad.extend(0b1000u64.to_le_bytes());
+        assert!(matches!(count_true(&bad), Err(CodingError::Malformed)));
+        assert!(matches!(
+            boolean_decode(&[1, 7]),
+            Err(CodingError::Malformed)
+        ));
+    }
+}
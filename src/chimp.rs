@@ -0,0 +1,339 @@
+// Chimp and Chimp128 float compression (Liakos et al., VLDB 2022).
+//
+// Like the Gorilla scheme in `float_xor`, each value is XORed with an earlier
+// one and only the meaningful bits of the XOR are stored, but a 2 bit flag
+// picks between:
+//   00  identical value
+//   01  many trailing zeros: leading zero bucket, length and center bits
+//   10  same leading zero bucket as last time: the bits after it
+//   11  new leading zero bucket: the bucket and the bits after it
+// Chimp128 XORs against whichever of the previous 128 values shares the
+// most trailing bits, found through a table keyed on the low bits, and
+// stores that value's slot next to the 00 and 01 flags.
+//
+// Both are generic over `FloatBits`; f32 uses narrower length fields and a
+// lower trailing zero threshold.
+//
+// Layout: varint value count | first value | flagged values
+
+use crate::{
+    bitpacking::{BitReader, BitWriter},
+    error::CodingError,
+    float_xor::{leading_zero_bucket, xor_zeros, FloatBits},
+    varint::{decode_u64, encode_u64},
+};
+
+const PREVIOUS_VALUES: usize = 128;
+const PREVIOUS_VALUES_LOG2: u32 = 7;
+
+const FLAG_SAME: u64 = 0b00;
+const FLAG_TRAILING: u64 = 0b01;
+const FLAG_SAME_LEADING: u64 = 0b10;
+const FLAG_NEW_LEADING: u64 = 0b11;
+
+// Sentinel meaning no leading zero bucket can be reused
+const NO_LEADING: u32 = u32::MAX;
+
+pub fn chimp_encode<F: FloatBits>(data: &[F]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    encode_u64(data.len() as u64, &mut encoded);
+    let Some(first) = data.first() else {
+        return encoded;
+    };
+
+    let mut writer = BitWriter::new();
+    writer.write(first.to_u64(), F::BITS);
+    let mut stored_leading = NO_LEADING;
+    let mut prev = first.to_u64();
+    for value in &data[1..] {
+        let value = value.to_u64();
+        write_xor::<F>(&mut writer, value ^ prev, &mut stored_leading, None);
+        prev = value;
+    }
+    encoded.extend(writer.finish());
+    encoded
+}
+
+pub fn chimp_decode<F: FloatBits>(data: &[u8]) -> Result<Vec<F>, CodingError> {
+    let (len, header_len) = decode_u64(data)?;
+    let mut reader = BitReader::new(&data[header_len..]);
+    let mut decoded = Vec::new();
+    if len == 0 {
+        return Ok(decoded);
+    }
+
+    let mut prev = reader.read(F::BITS)?;
+    decoded.push(F::from_u64(prev));
+    let mut stored_leading = NO_LEADING;
+    for _ in 1..len {
+        let (_, xor) = read_xor::<F>(&mut reader, &mut stored_leading, None)?;
+        prev ^= xor;
+        decoded.push(F::from_u64(prev));
+    }
+    Ok(decoded)
+}
+
+pub fn chimp128_encode<F: FloatBits>(data: &[F]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    encode_u64(data.len() as u64, &mut encoded);
+    let Some(first) = data.first() else {
+        return encoded;
+    };
+
+    let mut writer = BitWriter::new();
+    writer.write(first.to_u64(), F::BITS);
+    let mut stored_leading = NO_LEADING;
+    let mut previous = [0u64; PREVIOUS_VALUES];
+    // Most recent position of a value with the given low bits
+    let mut positions = vec![usize::MAX; 1 << lookup_bits::<F>()];
+    previous[0] = first.to_u64();
+    positions[lookup_key::<F>(first.to_u64())] = 0;
+
+    for (idx, value) in data.iter().enumerate().skip(1) {
+        let value = value.to_u64();
+        let key = lookup_key::<F>(value);
+        let candidate = positions[key];
+        let mut reference = (idx - 1) % PREVIOUS_VALUES;
+        if candidate != usize::MAX && idx - candidate < PREVIOUS_VALUES {
+            let slot = candidate % PREVIOUS_VALUES;
+            if (value ^ previous[slot]).trailing_zeros() > chimp128_threshold::<F>() {
+                reference = slot;
+            }
+        }
+        let xor = value ^ previous[reference];
+        write_xor::<F>(
+            &mut writer,
+            xor,
+            &mut stored_leading,
+            Some(reference as u64),
+        );
+
+        previous[idx % PREVIOUS_VALUES] = value;
+        positions[key] = idx;
+    }
+    encoded.extend(writer.finish());
+    encoded
+}
+
+pub fn chimp128_decode<F: FloatBits>(data: &[u8]) -> Result<Vec<F>, CodingError> {
+    let (len, header_len) = decode_u64(data)?;
+    let mut reader = BitReader::new(&data[header_len..]);
+    let mut decoded = Vec::new();
+    if len == 0 {
+        return Ok(decoded);
+    }
+
+    let mut previous = [0u64; PREVIOUS_VALUES];
+    previous[0] = reader.read(F::BITS)?;
+    decoded.push(F::from_u64(previous[0]));
+    let mut stored_leading = NO_LEADING;
+    for idx in 1..len as usize {
+        let prev_slot = (idx - 1) % PREVIOUS_VALUES;
+        let (slot, xor) = read_xor::<F>(&mut reader, &mut stored_leading, Some(prev_slot))?;
+        let value = previous[slot] ^ xor;
+        previous[idx % PREVIOUS_VALUES] = value;
+        decoded.push(F::from_u64(value));
+    }
+    Ok(decoded)
+}
+
+fn chimp128_threshold<F: FloatBits>() -> u32 {
+    F::TRAILING_THRESHOLD + PREVIOUS_VALUES_LOG2
+}
+
+// Low bits used to look up a previous value likely to share trailing bits
+fn lookup_bits<F: FloatBits>() -> u32 {
+    chimp128_threshold::<F>() + 1
+}
+
+fn lookup_key<F: FloatBits>(bits: u64) -> usize {
+    (bits & ((1 << lookup_bits::<F>()) - 1)) as usize
+}
+
+// Writes one flagged XOR. For Chimp128 `slot` is the reference value's slot,
+// which is stored after the 00 and 01 flags. The 10 and 11 flags are only
+// used when the reference is the previous value.
+fn write_xor<F: FloatBits>(
+    writer: &mut BitWriter,
+    xor: u64,
+    stored_leading: &mut u32,
+    slot: Option<u64>,
+) {
+    let threshold = match slot {
+        Some(_) => chimp128_threshold::<F>(),
+        None => F::TRAILING_THRESHOLD,
+    };
+    if xor == 0 {
+        writer.write(FLAG_SAME, 2);
+        if let Some(slot) = slot {
+            writer.write(slot, PREVIOUS_VALUES_LOG2);
+        }
+        *stored_leading = NO_LEADING;
+        return;
+    }
+
+    let (leading, trailing) = xor_zeros::<F>(xor);
+    let bucket = leading_zero_bucket::<F>(leading);
+    let leading = F::LEADING_ZERO_BUCKETS[bucket];
+    if trailing > threshold {
+        let significant = F::BITS - leading - trailing;
+        writer.write(FLAG_TRAILING, 2);
+        if let Some(slot) = slot {
+            writer.write(slot, PREVIOUS_VALUES_LOG2);
+        }
+        writer.write(bucket as u64, 3);
+        writer.write(significant as u64, F::SIGNIFICANT_BITS);
+        writer.write(xor >> trailing, significant);
+        *stored_leading = NO_LEADING;
+    } else if leading == *stored_leading {
+        writer.write(FLAG_SAME_LEADING, 2);
+        writer.write(xor, F::BITS - leading);
+    } else {
+        writer.write(FLAG_NEW_LEADING, 2);
+        writer.write(bucket as u64, 3);
+        writer.write(xor, F::BITS - leading);
+        *stored_leading = leading;
+    }
+}
+
+// Reads one flagged XOR, returning it with the slot of the value it applies
+// to. `prev_slot` is the slot of the previous value for Chimp128 and `None`
+// for Chimp, where the returned slot is unused.
+fn read_xor<F: FloatBits>(
+    reader: &mut BitReader,
+    stored_leading: &mut u32,
+    prev_slot: Option<usize>,
+) -> Result<(usize, u64), CodingError> {
+    let read_slot = |reader: &mut BitReader| -> Result<usize, CodingError> {
+        match prev_slot {
+            Some(_) => Ok(reader.read(PREVIOUS_VALUES_LOG2)? as usize),
+            None => Ok(0),
+        }
+    };
+    let flag = reader.read(2)?;
+    match flag {
+        FLAG_SAME => {
+            *stored_leading = NO_LEADING;
+            Ok((read_slot(reader)?, 0))
+        }
+        FLAG_TRAILING => {
+            let slot = read_slot(reader)?;
+            let leading = F::LEADING_ZERO_BUCKETS[reader.read(3)? as usize];
+            let significant = reader.read(F::SIGNIFICANT_BITS)? as u32;
+            if significant == 0 || leading + significant > F::BITS {
+                return Err(CodingError::Malformed);
+            }
+            let trailing = F::BITS - leading - significant;
+            *stored_leading = NO_LEADING;
+            Ok((slot, reader.read(significant)? << trailing))
+        }
+        FLAG_SAME_LEADING => {
+            if *stored_leading == NO_LEADING {
+                return Err(CodingError::Malformed);
+            }
+            let xor = reader.read(F::BITS - *stored_leading)?;
+            Ok((prev_slot.unwrap_or(0), xor))
+        }
+        _ => {
+            let leading = F::LEADING_ZERO_BUCKETS[reader.read(3)? as usize];
+            *stored_leading = leading;
+            let xor = reader.read(F::BITS - leading)?;
+            Ok((prev_slot.unwrap_or(0), xor))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor_data() -> Vec<f64> {
+        (0..2000)
+            .map(|x| ((x as f64) * 0.05).sin() * 20.0 + 50.0)
+            .map(|x| (x * 100.0).round() / 100.0)
+            .collect()
+    }
+
+    fn assert_bits_eq<F: FloatBits>(decoded: &[F], data: &[F]) {
+        let decoded: Vec<u64> = decoded.iter().map(|x| x.to_u64()).collect();
+        let data: Vec<u64> = data.iter().map(|x| x.to_u64()).collect();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_chimp_round_trip() {
+        let data = vec![12., 24., 15., 12., 35., 35., 35.5, -0.0, f64::NAN, 1e-300];
+        let encoded = chimp_encode(&data);
+        assert_bits_eq(&chimp_decode(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_chimp_repeated_values() {
+        let data = vec![21.5; 1000];
+        let encoded = chimp_encode(&data);
+        // 2 header bytes, the first value then 2 bits per repeat
+        assert_eq!(encoded.len(), 2 + 8 + 250);
+        assert_bits_eq(&chimp_decode(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_chimp128_round_trip() {
+        let data = sensor_data();
+        let encoded = chimp128_encode(&data);
+        assert_bits_eq(&chimp128_decode(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_chimp128_reuses_older_values() {
+        // A repeating pattern longer than one step back
+        let pattern: Vec<f64> = [2f64, 3., 5., 7., 11.].iter().map(|x| x.sqrt()).collect();
+        let data: Vec<f64> = pattern.iter().cycle().take(500).copied().collect();
+        let chimp = chimp_encode(&data);
+        let chimp128 = chimp128_encode(&data);
+        assert!(chimp128.len() * 4 < chimp.len());
+        assert_bits_eq(&chimp128_decode(&chimp128).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_f32_round_trip() {
+        let mut data: Vec<f32> = sensor_data().iter().map(|x| *x as f32).collect();
+        data.extend([f32::NAN, -0.0, f32::INFINITY, f32::MIN_POSITIVE, 0.0]);
+        let encoded = chimp_encode(&data);
+        assert_bits_eq(&chimp_decode::<f32>(&encoded).unwrap(), &data);
+        let encoded = chimp128_encode(&data);
+        assert_bits_eq(&chimp128_decode::<f32>(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_compresses_sensor_data() {
+        let data = sensor_data();
+        let encoded = chimp_encode(&data);
+        assert!(encoded.len() < data.len() * 8);
+        assert_bits_eq(&chimp_decode(&encoded).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_empty_and_truncated() {
+        assert!(chimp_decode::<f64>(&chimp_encode::<f64>(&[]))
+            .unwrap()
+            .is_empty());
+        assert!(chimp128_decode::<f64>(&chimp128_encode::<f64>(&[]))
+            .unwrap()
+            .is_empty());
+        let encoded = chimp_encode(&sensor_data());
+        assert!(matches!(
+            chimp_decode::<f64>(&encoded[..encoded.len() / 2]),
+            Err(CodingError::Truncated)
+        ));
+        // A count far past what the input holds
+        let huge = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        assert!(matches!(
+            chimp_decode::<f64>(&huge),
+            Err(CodingError::Truncated)
+        ));
+        assert!(matches!(
+            chimp128_decode::<f64>(&huge),
+            Err(CodingError::Truncated)
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+// Exact decimal column codec for fixed-point values such as money.
+//
+// Every value in a block is rescaled to the smallest scale that represents
+// all of them exactly, so the mantissas are plain integers (value * 10^scale).
+// Those are delta encoded, zigzag mapped and packed with simple8b. Decoding
+// returns `Decimal`s at the block scale, never a rounded float.
+//
+// Layout: varint value count | scale (1 byte) | simple8b words
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    delta_encode::{checked_delta_decode, delta_encode},
+    error::CodingError,
+    simple8b::{simple8b_from_bytes, simple8b_to_bytes},
+    varint::{encode_u64, take_u64, take_u8, zigzag_decode, zigzag_encode},
+};
+
+// 10^18 is the largest power of ten that fits an i64
+pub const MAX_SCALE: u8 = 18;
+// Keeps every delta's zigzag under simple8b's 60 bit limit
+const MAX_MANTISSA: i64 = (1 << 58) - 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    pub mantissa: i64,
+    pub scale: u8,
+}
+
+impl Decimal {
+    pub fn new(mantissa: i64, scale: u8) -> Result<Self, CodingError> {
+        if scale > MAX_SCALE {
+            return Err(CodingError::InvalidDecimal);
+        }
+        Ok(Self { mantissa, scale })
+    }
+
+    // Exact decimal of the shortest string that round trips to `value`
+    pub fn from_f64(value: f64) -> Result<Self, CodingError> {
+        if !value.is_finite() {
+            return Err(CodingError::InvalidDecimal);
+        }
+        value.to_string().parse()
+    }
+
+    // Nearest f64, which may not be exact
+    pub fn to_f64(self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    // Same value at the smallest scale, without trailing zeros
+    pub fn normalize(mut self) -> Self {
+        while self.scale > 0 && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+
+    // Same value at a scale no smaller than the current one
+    pub fn rescale(self, scale: u8) -> Result<Self, CodingError> {
+        if scale < self.scale || scale > MAX_SCALE {
+            return Err(CodingError::InvalidDecimal);
+        }
+        let mantissa = self
+            .mantissa
+            .checked_mul(10i64.pow((scale - self.scale) as u32))
+            .ok_or(CodingError::Overflow)?;
+        Ok(Self { mantissa, scale })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{sign}{digits}");
+        }
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        write!(f, "{sign}{int}.{frac}")
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = CodingError;
+
+    fn from_str(s: &str) -> Result<Self, CodingError> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        let all_digits = |x: &str| x.bytes().all(|b| b.is_ascii_digit());
+        if int.len() + frac.len() == 0 || !all_digits(int) || !all_digits(frac) {
+            return Err(CodingError::InvalidDecimal);
+        }
+        if frac.len() > MAX_SCALE as usize {
+            return Err(CodingError::InvalidDecimal);
+        }
+
+        let mut mantissa: i64 = 0;
+        for digit in int.bytes().chain(frac.bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|x| x.checked_add((digit - b'0') as i64))
+                .ok_or(CodingError::Overflow)?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+        Ok(Self {
+            mantissa,
+            scale: frac.len() as u8,
+        })
+    }
+}
+
+pub fn decimal_encode(data: &[Decimal]) -> Result<Vec<u8>, CodingError> {
+    let scale = data.iter().map(|x| x.normalize().scale).max().unwrap_or(0);
+    let mut mantissas = Vec::with_capacity(data.len());
+    for value in data {
+        let mantissa = value.normalize().rescale(scale)?.mantissa;
+        if mantissa.unsigned_abs() > MAX_MANTISSA as u64 {
+            return Err(CodingError::Overflow);
+        }
+        mantissas.push(mantissa);
+    }
+    let zigzagged: Vec<u64> = delta_encode(&mantissas)
+        .into_iter()
+        .map(zigzag_encode)
+        .collect();
+
+    let mut encoded = Vec::new();
+    encode_u64(data.len() as u64, &mut encoded);
+    encoded.push(scale);
+    simple8b_to_bytes(&zigzagged, &mut encoded);
+    Ok(encoded)
+}
+
+pub fn decimal_encode_f64(data: &[f64]) -> Result<Vec<u8>, CodingError> {
+    let decimals = data
+        .iter()
+        .map(|x| Decimal::from_f64(*x))
+        .collect::<Result<Vec<_>, _>>()?;
+    decimal_encode(&decimals)
+}
+
+pub fn decimal_encode_str<S: AsRef<str>>(data: &[S]) -> Result<Vec<u8>, CodingError> {
+    let decimals = data
+        .iter()
+        .map(|x| x.as_ref().parse())
+        .collect::<Result<Vec<Decimal>, _>>()?;
+    decimal_encode(&decimals)
+}
+
+pub fn decimal_decode(data: &[u8]) -> Result<Vec<Decimal>, CodingError> {
+    let mut buff = data;
+    let len = take_u64(&mut buff)? as usize;
+    let scale = take_u8(&mut buff)?;
+    if scale > MAX_SCALE {
+        return Err(CodingError::Malformed);
+    }
+    let deltas: Vec<i64> = simple8b_from_bytes(buff, len)?
+        .into_iter()
+        .map(zigzag_decode)
+        .collect();
+    Ok(checked_delta_decode(&deltas)?
+        .into_iter()
+        .map(|mantissa| Decimal { mantissa, scale })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple8b::simple8b_encode;
+
+    fn to_strings(data: &[Decimal]) -> Vec<String> {
+        data.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for s in ["0", "12.30", "-0.05", "1234567.891", "-42", ".5"] {
+            let decimal: Decimal = s.parse().unwrap();
+            let expected = if s == ".5" { "0.5" } else { s };
+            assert_eq!(decimal.to_string(), expected);
+        }
+        assert_eq!(
+            "-0.05".parse::<Decimal>().unwrap(),
+            Decimal::new(-5, 2).unwrap()
+        );
+        for s in ["", "-", ".", "1.2.3", "1e5", "12a", "0.1234567890123456789"] {
+            assert!(matches!(
+                s.parse::<Decimal>(),
+                Err(CodingError::InvalidDecimal)
+            ));
+        }
+        assert!(matches!(
+            "99999999999999999999".parse::<Decimal>(),
+            Err(CodingError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(
+            Decimal::from_f64(19.99).unwrap(),
+            Decimal::new(1999, 2).unwrap()
+        );
+        assert_eq!(
+            Decimal::from_f64(-0.1).unwrap(),
+            Decimal::new(-1, 1).unwrap()
+        );
+        assert_eq!(
+            Decimal::from_f64(300.0).unwrap(),
+            Decimal::new(300, 0).unwrap()
+        );
+        assert!(matches!(
+            Decimal::from_f64(f64::NAN),
+            Err(CodingError::InvalidDecimal)
+        ));
+    }
+
+    #[test]
+    fn test_f64_round_trip_is_exact() {
+        let data: Vec<f64> = (0..2000).map(|x| (x * 37 % 10000) as f64 / 100.0).collect();
+        let encoded = decimal_encode_f64(&data).unwrap();
+        let decoded = decimal_decode(&encoded).unwrap();
+        assert!(decoded.iter().all(|x| x.scale == 2));
+        let expected: Vec<String> = data.iter().map(|x| format!("{x:.2}")).collect();
+        assert_eq!(to_strings(&decoded), expected);
+    }
+
+    #[test]
+    fn test_minimal_scale() {
+        let encoded = decimal_encode_str(&["12.30", "1.5", "-7.000"]).unwrap();
+        let decoded = decimal_decode(&encoded).unwrap();
+        assert_eq!(to_strings(&decoded), ["12.3", "1.5", "-7.0"]);
+
+        let encoded = decimal_encode_str(&["100.00", "25"]).unwrap();
+        assert_eq!(
+            to_strings(&decimal_decode(&encoded).unwrap()),
+            ["100", "25"]
+        );
+    }
+
+    #[test]
+    fn test_compresses() {
+        let data: Vec<String> = (0..5000)
+            .map(|x| format!("{}.{:02}", 100 + x / 10, x % 100))
+            .collect();
+        let encoded = decimal_encode_str(&data).unwrap();
+        assert!(encoded.len() < data.len() * 2);
+        assert_eq!(to_strings(&decimal_decode(&encoded).unwrap()), data);
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert!(matches!(
+            decimal_encode_str(&["1.000000000000000001", "1000"]),
+            Err(CodingError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn test_empty() {
+        let encoded = decimal_encode(&[]).unwrap();
+        assert!(decimal_decode(&encoded).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_malformed() {
+        // One value, scale 2, then a word with a zero width selector
+        let zero_selector = [&[1, 2][..], &[0; 8]].concat();
+        assert!(matches!(
+            decimal_decode(&zero_selector),
+            Err(CodingError::Malformed)
+        ));
+
+        // Deltas that add up past i64::MAX
+        let mut overflow = vec![17, 0];
+        for word in simple8b_encode(&[zigzag_encode((1 << 59) - 1); 17]) {
+            overflow.extend(word.to_le_bytes());
+        }
+        assert!(matches!(
+            decimal_decode(&overflow),
+            Err(CodingError::Malformed)
+        ));
+        assert!(matches!(
+            decimal_decode(&overflow[..overflow.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+    }
+}
@@ -3,6 +3,8 @@ use std::{
     ops::{Add, Sub},
 };
 
+use crate::error::CodingError;
+
 pub fn delta_encode<T>(data: &[T]) -> Vec<T>
 where
     for<'a> &'a T: Sub<&'a T, Output = T>,
@@ -64,6 +66,18 @@ where
     return output;
 }
 
+// `delta_decode` for untrusted i64 deltas: a sum past the i64 range is an
+// error rather than a panic
+pub(crate) fn checked_delta_decode(data: &[i64]) -> Result<Vec<i64>, CodingError> {
+    let mut value = 0i64;
+    data.iter()
+        .map(|delta| {
+            value = value.checked_add(*delta).ok_or(CodingError::Malformed)?;
+            Ok(value)
+        })
+        .collect()
+}
+
 pub fn delta_delta_decode<T>(data: &[T]) -> Vec<T>
 where
     for<'a> &'a T: Add<&'a T, Output = T>,
@@ -78,9 +92,6 @@ where
     output.push(&cur_val + &cur_change);
     cur_val = &cur_val + &cur_change;
     for val in data[2..].iter() {
-        println!("val {:}", val);
-        println!("output {:?}", output);
-        println!("cur_val {:}", cur_val);
         cur_val = &(&cur_val + &cur_change) + val;
         output.push(cur_val.clone());
         cur_change = &cur_change + val;
@@ -100,6 +111,16 @@ mod tests {
         assert_eq!(truth, encoded_data)
     }
 
+    #[test]
+    fn test_checked_delta_decode() {
+        assert_eq!(checked_delta_decode(&[5, -2, 3]).unwrap(), vec![5, 3, 6]);
+        assert!(checked_delta_decode(&[]).unwrap().is_empty());
+        assert!(matches!(
+            checked_delta_decode(&[i64::MAX, 1]),
+            Err(CodingError::Malformed)
+        ));
+    }
+
     #[test]
     fn test_delta_delta() {
         let test_data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
@@ -1,16 +1,89 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    cmp::Ordering,
+    hash::{BuildHasher, Hash, RandomState},
+};
 
-use thiserror::Error;
+pub use crate::error::CodingError;
+use crate::{
+    bitmap::Bitmap,
+    simple8b::unpack,
+    varint::{encode_u64, encoded_len, take_bytes, take_u64, take_u8},
+};
 
-#[derive(Debug, Error)]
-pub enum CodingError {
-    #[error("Missing code in codebook")]
-    MissingCode,
+// A dictionary value with a binary form for `EncodingDict::to_bytes`
+pub trait DictEntry: Hash + Eq + Clone + Ord {
+    fn write_entry(&self, out: &mut Vec<u8>);
+    fn read_entry(data: &mut &[u8]) -> Result<Self, CodingError>;
+    // Bytes `write_entry` uses
+    fn entry_len(&self) -> usize;
 }
+
+// Integers are stored fixed width, little endian
+macro_rules! impl_int_entry {
+    ($($t:ty),*) => {
+        $(
+            impl DictEntry for $t {
+                fn write_entry(&self, out: &mut Vec<u8>) {
+                    out.extend(self.to_le_bytes());
+                }
+
+                fn read_entry(data: &mut &[u8]) -> Result<Self, CodingError> {
+                    let bytes = take_bytes(data, size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+
+                fn entry_len(&self) -> usize {
+                    size_of::<$t>()
+                }
+            }
+        )*
+    };
+}
+
+impl_int_entry!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+// Strings and byte strings are a varint length then the bytes
+impl DictEntry for Vec<u8> {
+    fn write_entry(&self, out: &mut Vec<u8>) {
+        encode_u64(self.len() as u64, out);
+        out.extend_from_slice(self);
+    }
+
+    fn read_entry(data: &mut &[u8]) -> Result<Self, CodingError> {
+        let len = take_u64(data)?;
+        let len = usize::try_from(len).map_err(|_| CodingError::Truncated)?;
+        Ok(take_bytes(data, len)?.to_vec())
+    }
+
+    fn entry_len(&self) -> usize {
+        encoded_len(self.len() as u64) + self.len()
+    }
+}
+
+impl DictEntry for String {
+    fn write_entry(&self, out: &mut Vec<u8>) {
+        encode_u64(self.len() as u64, out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn read_entry(data: &mut &[u8]) -> Result<Self, CodingError> {
+        String::from_utf8(Vec::<u8>::read_entry(data)?).map_err(|_| CodingError::InvalidUtf8)
+    }
+
+    fn entry_len(&self) -> usize {
+        encoded_len(self.len() as u64) + self.len()
+    }
+}
+
 pub struct EncodingDict<T> {
-    encoding_map: HashMap<T, u64>,
-    decoding_map: HashMap<u64, T>,
-    num_items: u64,
+    // Value of every code, so decoding is an index
+    values: Vec<T>,
+    // Open addressing index into `values` for encoding. Holds code + 1, with 0
+    // for an empty slot, and is kept at least twice as long as `values`
+    slots: Vec<u64>,
+    hasher: RandomState,
+    // Code order matches value order
+    sorted: bool,
 }
 
 impl<T> EncodingDict<T>
@@ -19,25 +92,198 @@ where
 {
     fn new() -> Self {
         return Self {
-            encoding_map: HashMap::new(),
-            decoding_map: HashMap::new(),
-            num_items: 0,
+            values: Vec::new(),
+            slots: Vec::new(),
+            hasher: RandomState::new(),
+            sorted: true,
         };
     }
 
+    // `k` must not be in the dictionary yet
     fn insert(&mut self, k: T) -> u64 {
-        self.encoding_map.insert(k.clone(), self.num_items);
-        self.decoding_map.insert(self.num_items, k.clone());
-        self.num_items += 1;
-        return self.num_items - 1;
+        // Only a sort can tell where a second value belongs
+        self.sorted &= self.values.is_empty();
+        if 2 * (self.values.len() + 1) > self.slots.len() {
+            self.rebuild_index((2 * self.slots.len()).max(16));
+        }
+        let code = self.values.len() as u64;
+        let slot = self.probe(&k).unwrap_err();
+        self.slots[slot] = code + 1;
+        self.values.push(k);
+        code
     }
 
     fn get_encode(&self, k: &T) -> Option<u64> {
-        return self.encoding_map.get(k).copied();
+        if self.slots.is_empty() {
+            return None;
+        }
+        self.probe(k).ok()
     }
 
     fn get_decode(&self, k: &u64) -> Option<T> {
-        return Some(self.decoding_map.get(k)?.clone());
+        self.values.get(*k as usize).cloned()
+    }
+
+    // Code of `k`, or the empty slot where it would go
+    fn probe(&self, k: &T) -> Result<u64, usize> {
+        let mask = self.slots.len() - 1;
+        let mut slot = self.hasher.hash_one(k) as usize & mask;
+        loop {
+            match self.slots[slot] {
+                0 => return Err(slot),
+                code if self.values[code as usize - 1] == *k => return Ok(code - 1),
+                _ => slot = (slot + 1) & mask,
+            }
+        }
+    }
+
+    fn rebuild_index(&mut self, len: usize) {
+        // An empty dictionary may not have a table yet, `insert` makes one
+        if len == 0 {
+            return;
+        }
+        let mask = len - 1;
+        let mut slots = vec![0; len];
+        for (code, value) in self.values.iter().enumerate() {
+            let mut slot = self.hasher.hash_one(value) as usize & mask;
+            while slots[slot] != 0 {
+                slot = (slot + 1) & mask;
+            }
+            slots[slot] = code as u64 + 1;
+        }
+        self.slots = slots;
+    }
+
+    // Reassigns codes in the order `compare` puts (value, old code) pairs in,
+    // returning the new code of every old one
+    fn reorder(&mut self, compare: impl FnMut(&(T, usize), &(T, usize)) -> Ordering) -> Vec<u64> {
+        let mut pairs: Vec<(T, usize)> = std::mem::take(&mut self.values)
+            .into_iter()
+            .zip(0..)
+            .collect();
+        pairs.sort_unstable_by(compare);
+        let mut remap = vec![0; pairs.len()];
+        for (new, (value, old)) in pairs.into_iter().enumerate() {
+            remap[old] = new as u64;
+            self.values.push(value);
+        }
+        self.rebuild_index(self.slots.len());
+        self.sorted = self.values.len() <= 1;
+        remap
+    }
+
+    // Drops every code from `len` on
+    fn truncate(&mut self, len: usize) {
+        self.values.truncate(len);
+        self.rebuild_index(self.slots.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    // Every value, indexed by its code
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    // Predicates are turned into the set of codes they match, a bitmap over
+    // `0..len()`, which `select_codes` and `select_simple8b` then test each
+    // row against without decoding the values
+    pub fn codes_eq(&self, value: &T) -> Bitmap {
+        self.codes_in(std::slice::from_ref(value))
+    }
+
+    pub fn codes_in(&self, values: &[T]) -> Bitmap {
+        let mut codes = Bitmap::new(self.len());
+        for value in values {
+            if let Some(code) = self.get_encode(value) {
+                codes.set(code as usize);
+            }
+        }
+        codes
+    }
+
+    pub fn codes_matching(&self, predicate: impl Fn(&T) -> bool) -> Bitmap {
+        let mut codes = Bitmap::new(self.len());
+        for (code, value) in self.values.iter().enumerate() {
+            if predicate(value) {
+                codes.set(code);
+            }
+        }
+        codes
+    }
+}
+
+impl<T> EncodingDict<T>
+where
+    T: Hash + Eq + Clone + AsRef<[u8]>,
+{
+    pub fn codes_with_prefix(&self, prefix: impl AsRef<[u8]>) -> Bitmap {
+        self.codes_matching(|value| value.as_ref().starts_with(prefix.as_ref()))
+    }
+}
+
+// Rows of `data` whose code is in `codes`, unknown codes never match
+pub fn select_codes(data: &[u64], codes: &Bitmap) -> Bitmap {
+    let mut selected = Bitmap::new(data.len());
+    for (row, code) in data.iter().enumerate() {
+        if codes.get(*code as usize) {
+            selected.set(row);
+        }
+    }
+    selected
+}
+
+// Like `select_codes` on `simple8b_encode`d codes, one word at a time
+pub fn select_simple8b(words: &[u64], codes: &Bitmap) -> Result<Bitmap, CodingError> {
+    // simple8b never writes the zero width selectors
+    if words.iter().any(|x| x >> 60 < 2) {
+        return Err(CodingError::Malformed);
+    }
+    let mut matches = Vec::new();
+    let mut row = 0;
+    for word in words {
+        for code in unpack(*word) {
+            if codes.get(code as usize) {
+                matches.push(row);
+            }
+            row += 1;
+        }
+    }
+    let mut selected = Bitmap::new(row);
+    for row in matches {
+        selected.set(row);
+    }
+    Ok(selected)
+}
+
+impl<T> EncodingDict<T>
+where
+    T: Hash + Eq + Clone + Ord,
+{
+    // Reassigns codes in value order, returning the new code of every old one
+    pub fn sort(&mut self) -> Vec<u64> {
+        let remap = self.reorder(|a, b| a.0.cmp(&b.0));
+        self.sorted = true;
+        remap
+    }
+
+    // First code whose value is >= `value`, so `x < value` exactly when
+    // `code(x) < lower_bound(value)`. None unless the dictionary is sorted
+    pub fn lower_bound(&self, value: &T) -> Option<u64> {
+        if !self.sorted {
+            return None;
+        }
+        Some(self.values.partition_point(|x| x < value) as u64)
     }
 }
 
@@ -59,6 +305,248 @@ where
     return (encoded_data, encoded_map);
 }
 
+impl<T> EncodingDict<T>
+where
+    T: DictEntry,
+{
+    // Layout: varint entry count | sorted flag (1 byte) | entries in code order
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encode_u64(self.values.len() as u64, &mut encoded);
+        encoded.push(self.sorted as u8);
+        for value in &self.values {
+            value.write_entry(&mut encoded);
+        }
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let len = take_u64(&mut buff)?;
+        let sorted = match take_u8(&mut buff)? {
+            0 => false,
+            1 => true,
+            _ => return Err(CodingError::Malformed),
+        };
+        let mut dict = Self::new();
+        for _ in 0..len {
+            let value = T::read_entry(&mut buff)?;
+            if dict.get_encode(&value).is_some() {
+                return Err(CodingError::DuplicateEntry);
+            }
+            dict.insert(value);
+        }
+        if !buff.is_empty() {
+            return Err(CodingError::Malformed);
+        }
+        if sorted {
+            if dict.values.windows(2).any(|x| x[0] > x[1]) {
+                return Err(CodingError::Unsorted);
+            }
+            dict.sorted = true;
+        }
+        Ok(dict)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum DictLimit {
+    // Most entries the dictionary may hold
+    Entries(usize),
+    // Most bytes its entries may take serialized
+    Bytes(usize),
+}
+
+// A block encoded by `encode_with_dict_limit`
+#[derive(Debug, PartialEq)]
+pub enum EncodedBlock<T> {
+    Dict(Vec<u64>),
+    // The dictionary would have outgrown its limit, so the values are kept as is
+    Plain(Vec<T>),
+}
+
+impl<T> EncodedBlock<T>
+where
+    T: DictEntry,
+{
+    // Layout: tag (0 dict, 1 plain) | varint count | varint codes or entries
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        match self {
+            EncodedBlock::Dict(codes) => {
+                encoded.push(0);
+                encode_u64(codes.len() as u64, &mut encoded);
+                for code in codes {
+                    encode_u64(*code, &mut encoded);
+                }
+            }
+            EncodedBlock::Plain(values) => {
+                encoded.push(1);
+                encode_u64(values.len() as u64, &mut encoded);
+                for value in values {
+                    value.write_entry(&mut encoded);
+                }
+            }
+        }
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let tag = take_u8(&mut buff)?;
+        let len = take_u64(&mut buff)?;
+        let block = match tag {
+            0 => EncodedBlock::Dict(
+                (0..len)
+                    .map(|_| take_u64(&mut buff))
+                    .collect::<Result<_, _>>()?,
+            ),
+            1 => EncodedBlock::Plain(
+                (0..len)
+                    .map(|_| T::read_entry(&mut buff))
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(CodingError::Malformed),
+        };
+        if !buff.is_empty() {
+            return Err(CodingError::Malformed);
+        }
+        Ok(block)
+    }
+}
+
+// Like `encode_with_dict`, but if the block's new values would take the
+// dictionary past `limit` they are rolled back and the block is stored plain,
+// as Parquet does. Later blocks can still use the dictionary as it was
+pub fn encode_with_dict_limit<T>(
+    data: &[T],
+    dict: &mut EncodingDict<T>,
+    limit: DictLimit,
+) -> EncodedBlock<T>
+where
+    T: DictEntry,
+{
+    let (old_len, old_sorted) = (dict.len(), dict.sorted);
+    let mut bytes: usize = match limit {
+        DictLimit::Bytes(_) => dict.values.iter().map(|x| x.entry_len()).sum(),
+        DictLimit::Entries(_) => 0,
+    };
+    let mut encoded = Vec::with_capacity(data.len());
+    for item in data {
+        if let Some(code) = dict.get_encode(item) {
+            encoded.push(code);
+            continue;
+        }
+        bytes += item.entry_len();
+        let full = match limit {
+            DictLimit::Entries(max) => dict.len() >= max,
+            DictLimit::Bytes(max) => bytes > max,
+        };
+        if full {
+            dict.truncate(old_len);
+            dict.sorted = old_sorted;
+            return EncodedBlock::Plain(data.to_vec());
+        }
+        encoded.push(dict.insert(item.clone()));
+    }
+    EncodedBlock::Dict(encoded)
+}
+
+// Two passes: the most frequent value gets code 0, the next code 1 and so on,
+// keeping codes small for `simple8b_encode`. Ties keep first-seen order
+pub fn frequency_dict_encode<T>(data: &[T]) -> (Vec<u64>, EncodingDict<T>)
+where
+    T: Hash + Eq + Clone,
+{
+    let (encoded, mut dict) = dict_encode(data);
+    let mut counts = vec![0usize; dict.len()];
+    for code in &encoded {
+        counts[*code as usize] += 1;
+    }
+    let remap = dict.reorder(|a, b| counts[b.1].cmp(&counts[a.1]).then(a.1.cmp(&b.1)));
+    let encoded = encoded.iter().map(|x| remap[*x as usize]).collect();
+    (encoded, dict)
+}
+
+// Codes are assigned in value order, so `code_a < code_b` exactly when `a < b`
+pub fn sorted_dict_encode<T>(data: &[T]) -> (Vec<u64>, EncodingDict<T>)
+where
+    T: Hash + Eq + Clone + Ord,
+{
+    let mut values: Vec<&T> = data.iter().collect();
+    values.sort_unstable();
+    values.dedup();
+    let mut dict = EncodingDict::<T>::new();
+    for value in values {
+        dict.insert(value.clone());
+    }
+    dict.sorted = true;
+    let encoded = encode_with_dict(data, &mut dict);
+    (encoded, dict)
+}
+
+// Encodes with a sorted dictionary, re-sorting it if `data` adds values. The
+// returned codes use the new order, and the remap table (see `remap_codes`)
+// moves data encoded before this call to it. No table means no code changed
+pub fn encode_with_sorted_dict<T>(
+    data: &[T],
+    dict: &mut EncodingDict<T>,
+) -> (Vec<u64>, Option<Vec<u64>>)
+where
+    T: Hash + Eq + Clone + Ord,
+{
+    let old_len = dict.len();
+    let encoded = encode_with_dict(data, dict);
+    if dict.sorted && dict.len() == old_len {
+        return (encoded, None);
+    }
+    let mut remap = dict.sort();
+    let encoded = encoded.iter().map(|x| remap[*x as usize]).collect();
+    remap.truncate(old_len);
+    (encoded, Some(remap))
+}
+
+// One dictionary holding the values of all of `dicts`, in first-seen order,
+// and a remap table per source for `remap_codes`, so chunks can be compacted
+// without decoding them
+pub fn merge_dicts<T>(dicts: &[&EncodingDict<T>]) -> (EncodingDict<T>, Vec<Vec<u64>>)
+where
+    T: Hash + Eq + Clone,
+{
+    let mut merged = EncodingDict::<T>::new();
+    let remaps = dicts
+        .iter()
+        .map(|dict| encode_with_dict(&dict.values, &mut merged))
+        .collect();
+    (merged, remaps)
+}
+
+// Like `merge_dicts`, but the merged dictionary is sorted
+pub fn merge_sorted_dicts<T>(dicts: &[&EncodingDict<T>]) -> (EncodingDict<T>, Vec<Vec<u64>>)
+where
+    T: Hash + Eq + Clone + Ord,
+{
+    let (mut merged, remaps) = merge_dicts(dicts);
+    let order = merged.sort();
+    let remaps = remaps
+        .into_iter()
+        .map(|remap| remap.iter().map(|x| order[*x as usize]).collect())
+        .collect();
+    (merged, remaps)
+}
+
+// Rewrites codes through a table from `EncodingDict::sort` or a merge
+pub fn remap_codes(data: &[u64], remap: &[u64]) -> Result<Vec<u64>, CodingError> {
+    data.iter()
+        .map(|x| {
+            remap
+                .get(*x as usize)
+                .copied()
+                .ok_or(CodingError::MissingCode)
+        })
+        .collect()
+}
+
 pub fn encode_with_dict<T>(data: &[T], dict: &mut EncodingDict<T>) -> Vec<u64>
 where
     T: Hash + Eq + Clone,
@@ -91,7 +579,15 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::*;
+    use crate::simple8b::simple8b_encode;
+
+    // Value to code view of the dictionary
+    fn encoding_map<T: Hash + Eq + Clone>(dict: &EncodingDict<T>) -> HashMap<T, u64> {
+        dict.values.iter().cloned().zip(0..).collect()
+    }
 
     #[test]
     fn test_dict_encode() {
@@ -107,7 +603,7 @@ mod tests {
             HashMap::<String, u64>::from([("test".to_string(), 0u64), ("other".to_string(), 1u64)]);
 
         assert_eq!(encoded, vec![0, 0, 1, 0, 1]);
-        assert_eq!(dict.encoding_map, expected_encoding);
+        assert_eq!(encoding_map(&dict), expected_encoding);
     }
 
     #[test]
@@ -155,7 +651,7 @@ mod tests {
             ("new".to_string(), 2u64),
         ]);
         assert_eq!(encoded, vec![0, 0, 1, 0, 2]);
-        assert_eq!(dict.encoding_map, expected_encoding)
+        assert_eq!(encoding_map(&dict), expected_encoding)
     }
     #[test]
     fn test_round_trip() {
@@ -167,8 +663,414 @@ mod tests {
             "other".to_string(),
         ];
         let (encoded, dict) = dict_encode(&raw_vec);
-        println!("{:?}", dict.decoding_map);
         let decoded = decode_with_dict(&encoded, &dict).expect("Should not have error in test");
         assert_eq!(raw_vec, decoded);
     }
+
+    #[test]
+    fn test_sorted_dict_encode() {
+        let raw_vec: Vec<String> = ["pear", "apple", "fig", "apple", "kiwi", "pear"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let (encoded, dict) = sorted_dict_encode(&raw_vec);
+        assert!(dict.is_sorted());
+        assert_eq!(encoded, vec![3, 0, 1, 0, 2, 3]);
+        for (a, b) in raw_vec.iter().zip(&raw_vec[1..]) {
+            let (code_a, code_b) = (dict.get_encode(a), dict.get_encode(b));
+            assert_eq!(code_a.cmp(&code_b), a.cmp(b));
+        }
+        assert_eq!(decode_with_dict(&encoded, &dict).unwrap(), raw_vec);
+    }
+
+    #[test]
+    fn test_first_seen_dict_is_not_sorted() {
+        let (_, dict) = dict_encode(&[3, 1, 2]);
+        assert!(!dict.is_sorted());
+        assert_eq!(dict.lower_bound(&2), None);
+    }
+
+    #[test]
+    fn test_lower_bound() {
+        let (_, dict) = sorted_dict_encode(&[10, 20, 30, 40]);
+        assert_eq!(dict.lower_bound(&5), Some(0));
+        assert_eq!(dict.lower_bound(&20), Some(1));
+        assert_eq!(dict.lower_bound(&25), Some(2));
+        assert_eq!(dict.lower_bound(&45), Some(4));
+    }
+
+    #[test]
+    fn test_encode_with_sorted_dict() {
+        let (old_codes, mut dict) = sorted_dict_encode(&[10, 30, 50, 30]);
+        let (encoded, remap) = encode_with_sorted_dict(&[30, 50], &mut dict);
+        assert_eq!(encoded, vec![1, 2]);
+        assert_eq!(remap, None);
+
+        let (encoded, remap) = encode_with_sorted_dict(&[40, 10, 5], &mut dict);
+        assert!(dict.is_sorted());
+        assert_eq!(encoded, vec![3, 1, 0]);
+        let remap = remap.unwrap();
+        assert_eq!(remap, vec![1, 2, 4]);
+        let old_codes = remap_codes(&old_codes, &remap).unwrap();
+        assert_eq!(old_codes, vec![1, 2, 4, 2]);
+        assert_eq!(
+            decode_with_dict(&old_codes, &dict).unwrap(),
+            vec![10, 30, 50, 30]
+        );
+    }
+
+    #[test]
+    fn test_sort_first_seen_dict() {
+        let raw_vec = vec!["b", "c", "a", "b"];
+        let (encoded, mut dict) = dict_encode(&raw_vec);
+        let remap = dict.sort();
+        assert_eq!(remap, vec![1, 2, 0]);
+        let encoded = remap_codes(&encoded, &remap).unwrap();
+        assert_eq!(encoded, vec![1, 2, 0, 1]);
+        assert_eq!(decode_with_dict(&encoded, &dict).unwrap(), raw_vec);
+    }
+
+    #[test]
+    fn test_sort_empty_dict() {
+        let (_, mut dict) = dict_encode::<i64>(&[]);
+        assert!(dict.sort().is_empty());
+        assert!(dict.is_sorted() && dict.is_empty());
+        let remap = dict.reorder(|a, b| a.1.cmp(&b.1));
+        assert!(remap.is_empty());
+        // The dictionary still takes values afterwards
+        assert_eq!(encode_with_dict(&[5, 3], &mut dict), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_remap_missing_code() {
+        assert!(matches!(
+            remap_codes(&[0, 3], &[1, 0]),
+            Err(CodingError::MissingCode)
+        ));
+    }
+
+    #[test]
+    fn test_serialize_strings() {
+        let raw_vec: Vec<String> = ["test", "other", "", "test", "ünïcode"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let (encoded, dict) = dict_encode(&raw_vec);
+        let bytes = dict.to_bytes();
+        assert_eq!(&bytes[..8], [4, 0, 4, b't', b'e', b's', b't', 5]);
+
+        let restored = EncodingDict::<String>::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.values, dict.values);
+        assert!(!restored.is_sorted());
+        assert_eq!(decode_with_dict(&encoded, &restored).unwrap(), raw_vec);
+    }
+
+    #[test]
+    fn test_serialize_integers() {
+        let (encoded, dict) = sorted_dict_encode(&[-5i32, 70000, 3, -5]);
+        let bytes = dict.to_bytes();
+        // Count, flag then three 4 byte entries
+        assert_eq!(bytes.len(), 2 + 3 * 4);
+        let restored = EncodingDict::<i32>::from_bytes(&bytes).unwrap();
+        assert!(restored.is_sorted());
+        assert_eq!(
+            decode_with_dict(&encoded, &restored).unwrap(),
+            vec![-5, 70000, 3, -5]
+        );
+    }
+
+    #[test]
+    fn test_serialize_bytes() {
+        let raw_vec = vec![vec![0u8, 255], vec![], vec![7; 300]];
+        let (encoded, dict) = dict_encode(&raw_vec);
+        let restored = EncodingDict::<Vec<u8>>::from_bytes(&dict.to_bytes()).unwrap();
+        assert_eq!(decode_with_dict(&encoded, &restored).unwrap(), raw_vec);
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let (_, dict) = dict_encode(&["a".to_string(), "b".to_string()]);
+        let bytes = dict.to_bytes();
+        assert!(matches!(
+            EncodingDict::<String>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            EncodingDict::<String>::from_bytes(&trailing),
+            Err(CodingError::Malformed)
+        ));
+        assert!(matches!(
+            EncodingDict::<String>::from_bytes(&[1, 0, 2, 0xC3, 0x28]),
+            Err(CodingError::InvalidUtf8)
+        ));
+        assert!(matches!(
+            EncodingDict::<u8>::from_bytes(&[2, 0, 9, 9]),
+            Err(CodingError::DuplicateEntry)
+        ));
+        assert!(matches!(
+            EncodingDict::<u8>::from_bytes(&[2, 1, 9, 3]),
+            Err(CodingError::Unsorted)
+        ));
+        assert!(matches!(
+            EncodingDict::<u8>::from_bytes(&[0, 2]),
+            Err(CodingError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_high_cardinality() {
+        let raw_vec: Vec<String> = (0..20_000)
+            .map(|x| format!("user-{}", x % 15_000))
+            .collect();
+        let (encoded, dict) = dict_encode(&raw_vec);
+        assert_eq!(dict.len(), 15_000);
+        assert!(dict.slots.len() >= 2 * dict.len());
+        assert_eq!(encoded[15_000..], encoded[..5_000]);
+        assert_eq!(dict.get_encode(&"user-14999".to_string()), Some(14_999));
+        assert_eq!(dict.get_encode(&"user-15000".to_string()), None);
+        assert_eq!(decode_with_dict(&encoded, &dict).unwrap(), raw_vec);
+    }
+
+    #[test]
+    fn test_empty_dict() {
+        let (encoded, dict) = dict_encode::<u32>(&[]);
+        assert!(encoded.is_empty() && dict.is_empty());
+        assert_eq!(dict.get_encode(&1), None);
+        assert!(matches!(
+            decode_with_dict(&[0], &dict),
+            Err(CodingError::MissingCode)
+        ));
+    }
+
+    #[test]
+    fn test_dict_limit_entries() {
+        let (_, mut dict) = dict_encode(&[1u32, 2, 3]);
+        let block = encode_with_dict_limit(&[3, 4, 5, 4], &mut dict, DictLimit::Entries(5));
+        assert_eq!(block, EncodedBlock::Dict(vec![2, 3, 4, 3]));
+
+        let block = encode_with_dict_limit(&[1, 6, 2], &mut dict, DictLimit::Entries(5));
+        assert_eq!(block, EncodedBlock::Plain(vec![1, 6, 2]));
+        // The dictionary is back to how it was before the block
+        assert_eq!(dict.len(), 5);
+        assert_eq!(dict.get_encode(&6), None);
+        assert_eq!(
+            encode_with_dict_limit(&[5, 1], &mut dict, DictLimit::Entries(5)),
+            EncodedBlock::Dict(vec![4, 0])
+        );
+    }
+
+    #[test]
+    fn test_dict_limit_bytes() {
+        // Request ids never repeat, so the dictionary only gets bigger
+        let ids: Vec<String> = (0..100)
+            .map(|x| format!("{:032x}", (x as u128).wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+            .collect();
+        let mut dict = EncodingDict::<String>::new();
+        let limit = DictLimit::Bytes(1024);
+        let first = encode_with_dict_limit(&ids[..20], &mut dict, limit);
+        assert!(matches!(first, EncodedBlock::Dict(_)));
+        // 20 ids of 33 bytes fit, 20 more would not
+        assert_eq!(dict.len(), 20);
+        let second = encode_with_dict_limit(&ids[20..40], &mut dict, limit);
+        assert_eq!(second, EncodedBlock::Plain(ids[20..40].to_vec()));
+        assert_eq!(dict.len(), 20);
+        assert_eq!(dict.to_bytes().len(), 2 + 20 * 33);
+    }
+
+    #[test]
+    fn test_dict_limit_exceeded_by_empty_dict() {
+        let mut dict = EncodingDict::<u32>::new();
+        let block = encode_with_dict_limit(&[1, 2], &mut dict, DictLimit::Entries(0));
+        assert_eq!(block, EncodedBlock::Plain(vec![1, 2]));
+        assert!(dict.is_empty());
+
+        let mut dict = EncodingDict::<String>::new();
+        let long = vec!["a much longer value".to_string()];
+        let block = encode_with_dict_limit(&long, &mut dict, DictLimit::Bytes(4));
+        assert_eq!(block, EncodedBlock::Plain(long));
+        assert!(dict.is_empty());
+        assert_eq!(encode_with_dict(&["b".to_string()], &mut dict), vec![0]);
+    }
+
+    #[test]
+    fn test_encoded_block_bytes() {
+        let blocks = [
+            EncodedBlock::Dict(vec![0, 300, 2]),
+            EncodedBlock::Plain(vec!["a".to_string(), "bc".to_string()]),
+        ];
+        for block in blocks {
+            let bytes = block.to_bytes();
+            assert_eq!(EncodedBlock::<String>::from_bytes(&bytes).unwrap(), block);
+        }
+        assert_eq!(
+            EncodedBlock::<u8>::Plain(vec![7, 8]).to_bytes(),
+            [1, 2, 7, 8]
+        );
+        assert!(matches!(
+            EncodedBlock::<u8>::from_bytes(&[2, 0]),
+            Err(CodingError::Malformed)
+        ));
+        assert!(matches!(
+            EncodedBlock::<u8>::from_bytes(&[0, 2, 1]),
+            Err(CodingError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_frequency_dict_encode() {
+        let raw_vec = vec!["rare", "common", "mid", "common", "mid", "common"];
+        let (encoded, dict) = frequency_dict_encode(&raw_vec);
+        assert_eq!(encoded, vec![2, 0, 1, 0, 1, 0]);
+        assert!(!dict.is_sorted());
+        assert_eq!(decode_with_dict(&encoded, &dict).unwrap(), raw_vec);
+    }
+
+    #[test]
+    fn test_frequency_dict_encode_empty() {
+        let (encoded, dict) = frequency_dict_encode::<i64>(&[]);
+        assert!(encoded.is_empty() && dict.is_empty());
+    }
+
+    #[test]
+    fn test_frequency_order_packs_smaller() {
+        // 200 one-off values arrive before the status that fills the column
+        let mut raw_vec: Vec<String> = (0..200).map(|x| format!("error-{x}")).collect();
+        for x in 0..20_000 {
+            raw_vec.push(if x % 100 == 0 { "retry" } else { "ok" }.to_string());
+        }
+        let (first_seen, _) = dict_encode(&raw_vec);
+        let (by_frequency, dict) = frequency_dict_encode(&raw_vec);
+        assert_eq!(dict.get_encode(&"ok".to_string()), Some(0));
+
+        let first_seen_words = simple8b_encode(&first_seen).len();
+        let frequency_words = simple8b_encode(&by_frequency).len();
+        // Codes 200 and 201 take 8 bits each, codes 0 and 1 only 1
+        assert!(frequency_words * 4 < first_seen_words);
+        assert_eq!(decode_with_dict(&by_frequency, &dict).unwrap(), raw_vec);
+    }
+
+    #[test]
+    fn test_merge_dicts() {
+        let chunks = [
+            vec!["get", "put", "get"],
+            vec!["delete", "get"],
+            vec!["put", "head", "delete"],
+        ];
+        let encoded: Vec<(Vec<u64>, EncodingDict<&str>)> =
+            chunks.iter().map(|x| dict_encode(x)).collect();
+        let dicts: Vec<&EncodingDict<&str>> = encoded.iter().map(|x| &x.1).collect();
+        let (merged, remaps) = merge_dicts(&dicts);
+        assert_eq!(merged.values, vec!["get", "put", "delete", "head"]);
+        assert_eq!(remaps, vec![vec![0, 1], vec![2, 0], vec![1, 3, 2]]);
+
+        for ((codes, _), (chunk, remap)) in encoded.iter().zip(chunks.iter().zip(&remaps)) {
+            let codes = remap_codes(codes, remap).unwrap();
+            assert_eq!(&decode_with_dict(&codes, &merged).unwrap(), chunk);
+        }
+    }
+
+    #[test]
+    fn test_merge_sorted_dicts() {
+        let (codes_a, dict_a) = sorted_dict_encode(&[30, 10, 30]);
+        let (codes_b, dict_b) = sorted_dict_encode(&[20, 40, 10]);
+        let (merged, remaps) = merge_sorted_dicts(&[&dict_a, &dict_b]);
+        assert!(merged.is_sorted());
+        assert_eq!(merged.values, vec![10, 20, 30, 40]);
+        assert_eq!(remaps, vec![vec![0, 2], vec![0, 1, 3]]);
+        let codes_a = remap_codes(&codes_a, &remaps[0]).unwrap();
+        let codes_b = remap_codes(&codes_b, &remaps[1]).unwrap();
+        assert_eq!(
+            decode_with_dict(&codes_a, &merged).unwrap(),
+            vec![30, 10, 30]
+        );
+        assert_eq!(
+            decode_with_dict(&codes_b, &merged).unwrap(),
+            vec![20, 40, 10]
+        );
+    }
+
+    #[test]
+    fn test_merge_no_dicts() {
+        let (merged, remaps) = merge_dicts::<String>(&[]);
+        assert!(merged.is_empty() && remaps.is_empty());
+        let (merged, remaps) = merge_sorted_dicts::<i64>(&[]);
+        assert!(merged.is_empty() && merged.is_sorted() && remaps.is_empty());
+    }
+
+    #[test]
+    fn test_merge_sorted_empty_dicts() {
+        let (_, empty_a) = sorted_dict_encode::<i64>(&[]);
+        let (_, empty_b) = dict_encode::<i64>(&[]);
+        let (merged, remaps) = merge_sorted_dicts(&[&empty_a, &empty_b]);
+        assert!(merged.is_empty() && merged.is_sorted());
+        assert_eq!(remaps, vec![Vec::<u64>::new(), Vec::new()]);
+    }
+
+    fn hosts() -> Vec<String> {
+        (0..1000).map(|x| format!("web-{}", x % 7)).collect()
+    }
+
+    #[test]
+    fn test_codes_eq_in() {
+        let (_, dict) = dict_encode(&hosts());
+        let codes = dict.codes_eq(&"web-3".to_string());
+        assert_eq!(codes.ones().collect::<Vec<usize>>(), vec![3]);
+        let codes = dict.codes_in(&["web-1".to_string(), "db-1".to_string(), "web-5".to_string()]);
+        assert_eq!(codes.ones().collect::<Vec<usize>>(), vec![1, 5]);
+        assert_eq!(dict.codes_eq(&"db-1".to_string()).count_ones(), 0);
+    }
+
+    #[test]
+    fn test_codes_with_prefix() {
+        let raw_vec = vec!["web-1", "db-1", "web-2", "cache"];
+        let (_, dict) = dict_encode(&raw_vec);
+        assert_eq!(
+            dict.codes_with_prefix("web-")
+                .ones()
+                .collect::<Vec<usize>>(),
+            vec![0, 2]
+        );
+        assert_eq!(dict.codes_with_prefix("").count_ones(), 4);
+        assert_eq!(dict.codes_with_prefix(b"d").count_ones(), 1);
+    }
+
+    #[test]
+    fn test_select_codes() {
+        let data = hosts();
+        let (encoded, dict) = dict_encode(&data);
+        let codes = dict.codes_in(&["web-3".to_string(), "web-4".to_string()]);
+        let selected = select_codes(&encoded, &codes);
+        assert_eq!(selected.len(), data.len());
+        let expected: Vec<usize> = (0..data.len())
+            .filter(|x| data[*x] == "web-3" || data[*x] == "web-4")
+            .collect();
+        assert_eq!(selected.ones().collect::<Vec<usize>>(), expected);
+
+        let words = simple8b_encode(&encoded);
+        assert_eq!(select_simple8b(&words, &codes).unwrap(), selected);
+        assert!(matches!(
+            select_simple8b(&[0], &codes),
+            Err(CodingError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_select_range_on_sorted_dict() {
+        let data = vec![40, 10, 30, 20, 50, 10];
+        let (encoded, dict) = sorted_dict_encode(&data);
+        // 20 <= x < 45
+        let (low, high) = (
+            dict.lower_bound(&20).unwrap(),
+            dict.lower_bound(&45).unwrap(),
+        );
+        let codes = dict.codes_matching(|x| (20..45).contains(x));
+        let selected = select_codes(&encoded, &codes);
+        assert_eq!(selected.ones().collect::<Vec<usize>>(), vec![0, 2, 3]);
+        let by_code: Vec<usize> = (0..data.len())
+            .filter(|x| (low..high).contains(&encoded[*x]))
+            .collect();
+        assert_eq!(by_code, vec![0, 2, 3]);
+    }
 }
@@ -0,0 +1,440 @@
+// Elias-Fano encoding of a non-decreasing sequence of u64.
+//
+// Each value is split into `low_width` low bits, packed as is, and the
+// remaining high bits, stored in unary: the `i`th value sets bit
+// `(value >> low_width) + i` of the high bit vector, so the high part is the
+// number of zeros before its one. Values are stored relative to the first
+// one, so with `range = last - first` that takes about 2 + log2(range / len)
+// bits per value and stays randomly accessible. Sampled positions of every
+// `SAMPLE_RATE`th one and zero keep `get` and `successor` to a short scan.
+//
+// Layout: varint value count | varint base | low width (1 byte) | packed low
+// bits | varint high bit count | high bit words (LE)
+// The samples aren't stored, they are rebuilt on load.
+
+use crate::{
+    bitpacking::{get, low_mask, pack},
+    error::CodingError,
+    varint::{encode_u64, take_bytes, take_u64, take_u8},
+};
+
+const SAMPLE_RATE: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EliasFano {
+    len: usize,
+    base: u64,
+    low_width: u8,
+    low_bits: Vec<u8>,
+    high_bits: Vec<u64>,
+    num_high_bits: usize,
+    // Position in `high_bits` of the `k * SAMPLE_RATE`th one
+    one_samples: Vec<usize>,
+    // Position in `high_bits` of the `k * SAMPLE_RATE`th zero
+    zero_samples: Vec<usize>,
+}
+
+impl EliasFano {
+    pub fn new(data: &[u64]) -> Result<Self, CodingError> {
+        if data.windows(2).any(|x| x[0] > x[1]) {
+            return Err(CodingError::Unsorted);
+        }
+        let len = data.len();
+        let base = data.first().copied().unwrap_or(0);
+        let data: Vec<u64> = data.iter().map(|x| x - base).collect();
+        let max = data.last().copied().unwrap_or(0);
+        let low_width = if len == 0 || max / (len as u64) == 0 {
+            0
+        } else {
+            (max / len as u64).ilog2() as u8
+        };
+
+        let lows: Vec<u64> = data.iter().map(|x| x & low_mask(low_width)).collect();
+        let num_high_bits = (max >> low_width) as usize + len + 1;
+        let mut high_bits = vec![0u64; num_high_bits.div_ceil(64)];
+        for (idx, value) in data.iter().enumerate() {
+            let pos = (value >> low_width) as usize + idx;
+            high_bits[pos / 64] |= 1 << (pos % 64);
+        }
+
+        let (one_samples, zero_samples) = samples(&high_bits, num_high_bits);
+        Ok(Self {
+            len,
+            base,
+            low_width,
+            low_bits: pack(&lows, low_width),
+            high_bits,
+            num_high_bits,
+            one_samples,
+            zero_samples,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encode_u64(self.len as u64, &mut encoded);
+        encode_u64(self.base, &mut encoded);
+        encoded.push(self.low_width);
+        encoded.extend_from_slice(&self.low_bits);
+        encode_u64(self.num_high_bits as u64, &mut encoded);
+        for word in &self.high_bits {
+            encoded.extend(word.to_le_bytes());
+        }
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let len = take_u64(&mut buff)? as usize;
+        let base = take_u64(&mut buff)?;
+        let low_width = take_u8(&mut buff)?;
+        if low_width >= 64 {
+            return Err(CodingError::Malformed);
+        }
+        let low_len = len
+            .checked_mul(low_width as usize)
+            .ok_or(CodingError::Malformed)?
+            .div_ceil(8);
+        let low_bits = take_bytes(&mut buff, low_len)?.to_vec();
+        let num_high_bits = take_u64(&mut buff)? as usize;
+        let high_bits: Vec<u64> =
+            take_bytes(&mut buff, num_high_bits.div_ceil(64).saturating_mul(8))?
+                .chunks_exact(8)
+                .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+                .collect();
+        if !buff.is_empty() {
+            return Err(CodingError::Malformed);
+        }
+
+        // Walk the ones to check there is one per value, each value fits in a
+        // u64 and none is smaller than the one before it
+        let (mut idx, mut last_pos, mut prev) = (0, None, 0);
+        for (word_idx, word) in high_bits.iter().enumerate() {
+            let mut word = *word;
+            while word != 0 {
+                let pos = word_idx * 64 + word.trailing_zeros() as usize;
+                word &= word - 1;
+                if idx >= len || pos >= num_high_bits {
+                    return Err(CodingError::Malformed);
+                }
+                let high = (pos - idx) as u64;
+                if high > u64::MAX >> low_width {
+                    return Err(CodingError::Malformed);
+                }
+                let value = (high << low_width) | get(&low_bits, low_width, idx);
+                if base.checked_add(value).is_none() {
+                    return Err(CodingError::Malformed);
+                }
+                if value < prev {
+                    return Err(CodingError::Unsorted);
+                }
+                prev = value;
+                last_pos = Some(pos);
+                idx += 1;
+            }
+        }
+        // `new` ends the high bits with a single zero after the last one
+        if idx != len || num_high_bits != last_pos.map_or(1, |x| x + 2) {
+            return Err(CodingError::Malformed);
+        }
+
+        let (one_samples, zero_samples) = samples(&high_bits, num_high_bits);
+        Ok(Self {
+            len,
+            base,
+            low_width,
+            low_bits,
+            high_bits,
+            num_high_bits,
+            one_samples,
+            zero_samples,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<u64> {
+        if idx >= self.len {
+            return None;
+        }
+        let high = (self.select(idx, true) - idx) as u64;
+        let low = get(&self.low_bits, self.low_width, idx);
+        Some(self.base + ((high << self.low_width) | low))
+    }
+
+    // Index and value of the first element that is >= `x`
+    pub fn successor(&self, x: u64) -> Option<(usize, u64)> {
+        let bucket = (x.saturating_sub(self.base) >> self.low_width) as usize;
+        // Everything before the `bucket`th zero has a smaller high part
+        let zeros = self.num_high_bits - self.len;
+        let mut idx = match bucket {
+            0 => 0,
+            _ if bucket > zeros => return None,
+            _ => self.select(bucket - 1, false) + 1 - bucket,
+        };
+        while idx < self.len {
+            let value = self.get(idx).unwrap();
+            if value >= x {
+                return Some((idx, value));
+            }
+            idx += 1;
+        }
+        None
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            ef: self,
+            idx: 0,
+            pos: 0,
+        }
+    }
+
+    // Position of the `rank`th one (or zero) in `high_bits`
+    fn select(&self, rank: usize, ones: bool) -> usize {
+        let samples = if ones {
+            &self.one_samples
+        } else {
+            &self.zero_samples
+        };
+        let start = samples[rank / SAMPLE_RATE];
+        let mut remaining = rank % SAMPLE_RATE;
+        let mut word_idx = start / 64;
+        // Drop the bits before the sampled position
+        let mut word = self.word(word_idx, ones) & (u64::MAX << (start % 64));
+        loop {
+            let count = word.count_ones() as usize;
+            if remaining < count {
+                return word_idx * 64 + select_in_word(word, remaining);
+            }
+            remaining -= count;
+            word_idx += 1;
+            word = self.word(word_idx, ones);
+        }
+    }
+
+    fn word(&self, idx: usize, ones: bool) -> u64 {
+        if ones {
+            self.high_bits[idx]
+        } else {
+            !self.high_bits[idx]
+        }
+    }
+}
+
+pub struct Iter<'a> {
+    ef: &'a EliasFano,
+    idx: usize,
+    // Bit position in the high bits just after the previous value's one
+    pos: usize,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.idx >= self.ef.len {
+            return None;
+        }
+        let mut word_idx = self.pos / 64;
+        let mut word = self.ef.high_bits[word_idx] & (u64::MAX << (self.pos % 64));
+        while word == 0 {
+            word_idx += 1;
+            word = self.ef.high_bits[word_idx];
+        }
+        let pos = word_idx * 64 + word.trailing_zeros() as usize;
+        let high = (pos - self.idx) as u64;
+        let low = get(&self.ef.low_bits, self.ef.low_width, self.idx);
+        self.idx += 1;
+        self.pos = pos + 1;
+        Some(self.ef.base + ((high << self.ef.low_width) | low))
+    }
+}
+
+// Positions of every `SAMPLE_RATE`th one and zero in `high_bits`
+fn samples(high_bits: &[u64], num_high_bits: usize) -> (Vec<usize>, Vec<usize>) {
+    let mut one_samples = Vec::new();
+    let mut zero_samples = Vec::new();
+    let (mut ones, mut zeros) = (0, 0);
+    for pos in 0..num_high_bits {
+        if high_bits[pos / 64] & (1 << (pos % 64)) != 0 {
+            if ones % SAMPLE_RATE == 0 {
+                one_samples.push(pos);
+            }
+            ones += 1;
+        } else {
+            if zeros % SAMPLE_RATE == 0 {
+                zero_samples.push(pos);
+            }
+            zeros += 1;
+        }
+    }
+    (one_samples, zero_samples)
+}
+
+// Position of the `rank`th set bit of `word`
+fn select_in_word(mut word: u64, rank: usize) -> usize {
+    for _ in 0..rank {
+        word &= word - 1;
+    }
+    word.trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamps() -> Vec<u64> {
+        (0..5000u64)
+            .map(|x| 1_700_000_000_000 + x * 1000 + (x * 7919) % 997)
+            .collect()
+    }
+
+    #[test]
+    fn test_get() {
+        let data = vec![2, 3, 5, 7, 11, 13, 24];
+        let ef = EliasFano::new(&data).unwrap();
+        assert_eq!(ef.len(), 7);
+        for (idx, value) in data.iter().enumerate() {
+            assert_eq!(ef.get(idx), Some(*value));
+        }
+        assert_eq!(ef.get(7), None);
+    }
+
+    #[test]
+    fn test_large_round_trip() {
+        let data = timestamps();
+        let ef = EliasFano::new(&data).unwrap();
+        for (idx, value) in data.iter().enumerate() {
+            assert_eq!(ef.get(idx), Some(*value));
+        }
+        assert_eq!(ef.iter().collect::<Vec<u64>>(), data);
+        // Deltas are around 1000, so 9 low bits and 2 or 3 high bits
+        assert_eq!(ef.low_width, 9);
+        let size_bits = (ef.low_bits.len() + ef.high_bits.len() * 8) * 8;
+        assert!(size_bits <= data.len() * 12);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let data = vec![0, 0, 1, 1, 1, 9, 9, 1000];
+        let ef = EliasFano::new(&data).unwrap();
+        assert_eq!(ef.iter().collect::<Vec<u64>>(), data);
+        assert_eq!(ef.successor(1), Some((2, 1)));
+        assert_eq!(ef.successor(2), Some((5, 9)));
+    }
+
+    #[test]
+    fn test_successor() {
+        let data = timestamps();
+        let ef = EliasFano::new(&data).unwrap();
+        for x in [0, data[0], data[1] - 1, data[2500] + 1, data[4999]] {
+            let expected = data.iter().position(|v| *v >= x).unwrap();
+            assert_eq!(ef.successor(x), Some((expected, data[expected])));
+        }
+        assert_eq!(ef.successor(data[4999] + 1), None);
+        assert_eq!(ef.successor(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_empty() {
+        let ef = EliasFano::new(&[]).unwrap();
+        assert!(ef.is_empty());
+        assert_eq!(ef.get(0), None);
+        assert_eq!(ef.successor(0), None);
+        assert_eq!(ef.iter().next(), None);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        for data in [
+            timestamps(),
+            vec![0, 0, 1, 1, 1, 9, 9, 1000],
+            vec![u64::MAX],
+            Vec::new(),
+        ] {
+            let ef = EliasFano::new(&data).unwrap();
+            let loaded = EliasFano::from_bytes(&ef.to_bytes()).unwrap();
+            assert_eq!(loaded, ef);
+            assert_eq!(loaded.iter().collect::<Vec<u64>>(), data);
+        }
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let bytes = EliasFano::new(&timestamps()).unwrap().to_bytes();
+        assert!(matches!(
+            EliasFano::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(matches!(
+            EliasFano::from_bytes(&extra),
+            Err(CodingError::Malformed)
+        ));
+
+        // [1, 5] is stored relative to 1 with one low bit, so lows 0, 0 and
+        // highs 0, 2 at positions 0 and 3 of 5 high bits
+        assert_eq!(
+            EliasFano::new(&[1, 5]).unwrap().to_bytes(),
+            raw(2, 1, 1, &[0], 5, 0b1001)
+        );
+        // Lows out of order within a bucket
+        assert!(matches!(
+            EliasFano::from_bytes(&raw(2, 1, 1, &[0b01], 3, 0b011)),
+            Err(CodingError::Unsorted)
+        ));
+        // One value short, a one past the high bit count, no final zero
+        for bad in [
+            raw(2, 1, 1, &[0], 5, 0b0001),
+            raw(2, 1, 1, &[0], 3, 0b1001),
+            raw(2, 1, 1, &[0], 4, 0b1001),
+        ] {
+            assert!(matches!(
+                EliasFano::from_bytes(&bad),
+                Err(CodingError::Malformed)
+            ));
+        }
+        // A value past u64::MAX and a low width that can't shift the high part
+        for bad in [raw(1, u64::MAX, 0, &[], 3, 0b010), raw(0, 0, 64, &[], 1, 0)] {
+            assert!(matches!(
+                EliasFano::from_bytes(&bad),
+                Err(CodingError::Malformed)
+            ));
+        }
+    }
+
+    // Serialized form with a single high bit word
+    fn raw(
+        len: u64,
+        base: u64,
+        low_width: u8,
+        lows: &[u8],
+        num_high_bits: u64,
+        word: u64,
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode_u64(len, &mut bytes);
+        encode_u64(base, &mut bytes);
+        bytes.push(low_width);
+        bytes.extend_from_slice(lows);
+        encode_u64(num_high_bits, &mut bytes);
+        bytes.extend(word.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_unsorted() {
+        assert!(matches!(
+            EliasFano::new(&[1, 3, 2]),
+            Err(CodingError::Unsorted)
+        ));
+    }
+}
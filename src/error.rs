@@ -0,0 +1,29 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CodingError {
+    #[error("Missing code in codebook")]
+    MissingCode,
+    #[error("Input ended in the middle of a value")]
+    Truncated,
+    #[error("Varint is encoded with more bytes than needed")]
+    Overlong,
+    #[error("Varint does not fit in 64 bits")]
+    Overflow,
+    #[error("Input is not sorted")]
+    Unsorted,
+    #[error("Encoded data is malformed")]
+    Malformed,
+    #[error("Error bound can't be met for this data")]
+    InvalidErrorBound,
+    #[error("Value is not a valid decimal")]
+    InvalidDecimal,
+    #[error("Dictionary entry is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("Dictionary entry appears more than once")]
+    DuplicateEntry,
+    #[error("Restart interval must be positive")]
+    InvalidRestartInterval,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
@@ -1,91 +1,259 @@
-use itertools::Itertools;
+// Gorilla style XOR compression of floats (Pelkonen et al., VLDB 2015).
+//
+// Each value is XORed with the previous one. Equal values store a single 0
+// bit, otherwise only the meaningful bits between the XOR's leading and
+// trailing zeros are kept:
+//   0   identical value
+//   10  meaningful bits fit the previous window: the bits in that window
+//   11  new window: leading zeros, meaningful length and the meaningful bits
+//
+// The codec is generic over `FloatBits`, so f32 columns get their own field
+// widths rather than being widened to f64.
+//
+// Layout: varint value count | first value | flagged values
 
-fn xor_float_encode(data: &[f64]) -> Vec<u64> {
-    let mut encoded_data: Vec<u64> = Vec::new();
-    let first_value = data.first().unwrap().clone().to_bits();
-    let tuples: Vec<(&f64, &f64)> = data.iter().tuple_windows().collect();
+use crate::{
+    bitpacking::{BitReader, BitWriter},
+    error::CodingError,
+    varint::{decode_u64, encode_u64},
+};
 
-    let xored: Vec<u64> = tuples
-        .iter()
-        .map(|(a, b)| a.to_bits() ^ b.to_bits())
-        .collect();
-    println!("xored {:#^016x?}", xored);
-    encoded_data.push(first_value);
-    for word in xored {
-        if word == 0 {
-            encoded_data.push(word);
-        } else {
-        }
+// Bit level view of a float type for the XOR based codecs
+pub trait FloatBits: Copy {
+    const BITS: u32;
+    // Width of the leading zero count in a Gorilla window, larger counts are
+    // rounded down to fit
+    const LEADING_ZEROS_BITS: u32;
+    // Width of a meaningful bit count, a full width count is stored as 0
+    const SIGNIFICANT_BITS: u32;
+    // Trailing zeros needed before Chimp stores only the center bits
+    const TRAILING_THRESHOLD: u32;
+    // Leading zero counts Chimp can store, indexed by their 3 bit code
+    const LEADING_ZERO_BUCKETS: [u32; 8];
+
+    // The raw bits in the low `BITS` bits
+    fn to_u64(self) -> u64;
+    fn from_u64(bits: u64) -> Self;
+}
+
+impl FloatBits for f64 {
+    const BITS: u32 = 64;
+    const LEADING_ZEROS_BITS: u32 = 5;
+    const SIGNIFICANT_BITS: u32 = 6;
+    const TRAILING_THRESHOLD: u32 = 6;
+    const LEADING_ZERO_BUCKETS: [u32; 8] = [0, 8, 12, 16, 18, 20, 22, 24];
+
+    fn to_u64(self) -> u64 {
+        self.to_bits()
+    }
+
+    fn from_u64(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+}
+
+impl FloatBits for f32 {
+    const BITS: u32 = 32;
+    const LEADING_ZEROS_BITS: u32 = 4;
+    const SIGNIFICANT_BITS: u32 = 5;
+    const TRAILING_THRESHOLD: u32 = 5;
+    // The f64 buckets scaled to half the width
+    const LEADING_ZERO_BUCKETS: [u32; 8] = [0, 4, 6, 8, 9, 10, 11, 12];
+
+    fn to_u64(self) -> u64 {
+        self.to_bits() as u64
+    }
+
+    fn from_u64(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
     }
+}
 
-    let mut last_xor: u64 = 0;
+// Leading and trailing zeros of the XOR of two `F`s, the bits between them
+// are the meaningful ones that need to be stored
+pub(crate) fn xor_zeros<F: FloatBits>(xor: u64) -> (u32, u32) {
+    (xor.leading_zeros() - (64 - F::BITS), xor.trailing_zeros())
+}
 
-    for i in 0..data.len() - 1 {
-        let first = data[i];
-        let second = data[i + 1];
-        let xored = first.to_bits() ^ second.to_bits();
+// Code of the largest bucket that is no more than `leading` zeros
+pub(crate) fn leading_zero_bucket<F: FloatBits>(leading: u32) -> usize {
+    F::LEADING_ZERO_BUCKETS
+        .iter()
+        .rposition(|bucket| *bucket <= leading)
+        .unwrap()
+}
 
-        println!("comparing {} and {}", first.to_bits(), second.to_bits());
+// How often each control prefix was written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XorControls {
+    pub identical: usize,
+    pub reused_window: usize,
+    pub new_window: usize,
+}
+
+pub fn xor_float_encode<F: FloatBits>(data: &[F]) -> Vec<u8> {
+    xor_float_encode_counted(data).0
+}
 
-        let mut last_xored_leading_zeros = 0;
-        let mut last_xored_trailing_zeros = 0;
+pub(crate) fn xor_float_encode_counted<F: FloatBits>(data: &[F]) -> (Vec<u8>, XorControls) {
+    let mut encoded = Vec::new();
+    let mut controls = XorControls::default();
+    encode_u64(data.len() as u64, &mut encoded);
+    let Some(first) = data.first() else {
+        return (encoded, controls);
+    };
 
-        // First iteration need to just compare with the actual value
-        if i == 0 {
-            last_xored_leading_zeros = data[0].to_bits().leading_zeros() as u64;
-            last_xored_trailing_zeros = data[0].to_bits().trailing_zeros() as u64;
-        } else {
-            last_xored_leading_zeros = last_xor.leading_zeros() as u64;
-            last_xored_trailing_zeros = last_xor.trailing_zeros() as u64;
+    let mut writer = BitWriter::new();
+    writer.write(first.to_u64(), F::BITS);
+    let max_leading = (1 << F::LEADING_ZEROS_BITS) - 1;
+    // Leading and trailing zeros around the last stored meaningful bits
+    let mut window: Option<(u32, u32)> = None;
+    let mut prev = first.to_u64();
+    for value in &data[1..] {
+        let value = value.to_u64();
+        let xor = value ^ prev;
+        prev = value;
+        if xor == 0 {
+            writer.write(0, 1);
+            controls.identical += 1;
+            continue;
         }
+        writer.write(1, 1);
 
-        // If the xored value is zero (the values are equal) then we just store 0
-        if xored == 0 {
-            encoded_data.push(0);
-        } else {
-            // Count the leading and trailing zeros so we know if all meaningful bits overlap
-            let xored_leading_zeros = xored.leading_zeros() as u64;
-            let xored_trailing_zeros = xored.trailing_zeros() as u64;
-
-            // If the meaningful bits overlap then we don't have to do anything special
-            if xored_leading_zeros >= last_xored_leading_zeros
-                && xored_trailing_zeros >= last_xored_trailing_zeros
+        let (leading, trailing) = xor_zeros::<F>(xor);
+        match window {
+            Some((window_leading, window_trailing))
+                if leading >= window_leading && trailing >= window_trailing =>
             {
-                let meaningful_data = second.to_bits() >> xored_trailing_zeros;
-                let meaningful_len = 64 - xored_trailing_zeros - xored_leading_zeros;
-                let encoded_word = (2 << meaningful_len) | meaningful_data;
-                encoded_data.push(encoded_word);
-            } else {
-                // Extra data needs to be encoded when the meaningful bits don't overlap
-                let meaningful_data = second.to_bits() >> xored_trailing_zeros;
-                let meaningful_len = 64 - xored_trailing_zeros - xored_leading_zeros;
-                let mut encoded_word = (xored_leading_zeros << (6 + meaningful_len))
-                    | (meaningful_len << meaningful_len)
-                    | meaningful_data;
-                encoded_word = encoded_word | (3 << (64 - encoded_word.leading_zeros()));
-                encoded_data.push(encoded_word);
+                writer.write(0, 1);
+                controls.reused_window += 1;
+                let significant = F::BITS - window_leading - window_trailing;
+                writer.write(xor >> window_trailing, significant);
+            }
+            _ => {
+                let leading = leading.min(max_leading);
+                let significant = F::BITS - leading - trailing;
+                writer.write(1, 1);
+                controls.new_window += 1;
+                writer.write(leading as u64, F::LEADING_ZEROS_BITS);
+                writer.write((significant % F::BITS) as u64, F::SIGNIFICANT_BITS);
+                writer.write(xor >> trailing, significant);
+                window = Some((leading, trailing));
             }
         }
+    }
+    encoded.extend(writer.finish());
+    (encoded, controls)
+}
 
-        last_xor = xored;
+pub fn xor_float_decode<F: FloatBits>(data: &[u8]) -> Result<Vec<F>, CodingError> {
+    let (len, header_len) = decode_u64(data)?;
+    let mut reader = BitReader::new(&data[header_len..]);
+    let mut decoded = Vec::new();
+    if len == 0 {
+        return Ok(decoded);
+    }
+
+    let mut prev = reader.read(F::BITS)?;
+    decoded.push(F::from_u64(prev));
+    let mut window: Option<(u32, u32)> = None;
+    for _ in 1..len {
+        if reader.read(1)? == 1 {
+            if reader.read(1)? == 1 {
+                let leading = reader.read(F::LEADING_ZEROS_BITS)? as u32;
+                let significant = match reader.read(F::SIGNIFICANT_BITS)? as u32 {
+                    0 => F::BITS,
+                    x => x,
+                };
+                if leading + significant > F::BITS {
+                    return Err(CodingError::Malformed);
+                }
+                window = Some((leading, F::BITS - leading - significant));
+            }
+            let (leading, trailing) = window.ok_or(CodingError::Malformed)?;
+            prev ^= reader.read(F::BITS - leading - trailing)? << trailing;
+        }
+        decoded.push(F::from_u64(prev));
     }
-    return encoded_data;
+    Ok(decoded)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn assert_bits_eq<F: FloatBits>(decoded: &[F], data: &[F]) {
+        let decoded: Vec<u64> = decoded.iter().map(|x| x.to_u64()).collect();
+        let data: Vec<u64> = data.iter().map(|x| x.to_u64()).collect();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_leading_zero_bucket() {
+        assert_eq!(leading_zero_bucket::<f64>(0), 0);
+        assert_eq!(leading_zero_bucket::<f64>(11), 1);
+        assert_eq!(leading_zero_bucket::<f64>(12), 2);
+        assert_eq!(leading_zero_bucket::<f64>(19), 4);
+        assert_eq!(leading_zero_bucket::<f64>(64), 7);
+        assert_eq!(leading_zero_bucket::<f32>(5), 1);
+        assert_eq!(leading_zero_bucket::<f32>(9), 4);
+        assert_eq!(leading_zero_bucket::<f32>(32), 7);
+    }
+
+    #[test]
+    fn test_xor_zeros() {
+        assert_eq!(xor_zeros::<f64>(0x0000_0F00_0000_0000), (20, 40));
+        assert_eq!(xor_zeros::<f32>(0x00F0_0000), (8, 20));
+    }
+
     #[test]
-    #[ignore = "code not yet working"]
     fn test_xor() {
         let test_data = vec![12., 24., 15., 12., 35.];
         let encoded = xor_float_encode(&test_data);
-        println!("Test data");
-        for d in test_data {
-            println!("{:016x}", d.to_bits());
-        }
-        println!("Encoded: {:016x?}", encoded);
-        assert!(false);
+        let decoded = xor_float_decode(&encoded).expect("Should not have error in test");
+        assert_bits_eq(&decoded, &test_data);
+    }
+
+    #[test]
+    fn test_xor_repeated_values() {
+        let test_data = vec![1.5f64; 81];
+        let encoded = xor_float_encode(&test_data);
+        // 1 byte count, the first value then a bit per repeat
+        assert_eq!(encoded.len(), 1 + 8 + 10);
+        assert_bits_eq(&xor_float_decode(&encoded).unwrap(), &test_data);
+    }
+
+    #[test]
+    fn test_f64_round_trip() {
+        let mut test_data: Vec<f64> = (0..1000).map(|x| (x as f64 * 0.01).cos() * 80.0).collect();
+        test_data.extend([f64::NAN, -0.0, f64::INFINITY, f64::MIN_POSITIVE, f64::MAX]);
+        let encoded = xor_float_encode(&test_data);
+        assert_bits_eq(&xor_float_decode(&encoded).unwrap(), &test_data);
+    }
+
+    #[test]
+    fn test_f32_round_trip() {
+        let mut test_data: Vec<f32> = (0..1000).map(|x| (x as f32 * 0.01).cos() * 80.0).collect();
+        test_data.extend([f32::NAN, -0.0, f32::INFINITY, f32::MIN_POSITIVE, f32::MAX]);
+        let encoded = xor_float_encode(&test_data);
+        assert_bits_eq(&xor_float_decode(&encoded).unwrap(), &test_data);
+    }
+
+    #[test]
+    fn test_f32_smaller_than_widened() {
+        let test_data: Vec<f32> = (0..1000).map(|x| 20.0 + (x % 50) as f32 * 0.25).collect();
+        let widened: Vec<f64> = test_data.iter().map(|x| *x as f64).collect();
+        let narrow = xor_float_encode(&test_data);
+        assert!(narrow.len() < xor_float_encode(&widened).len());
+        assert_bits_eq(&xor_float_decode(&narrow).unwrap(), &test_data);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let encoded = xor_float_encode(&[1.0f32, 2.5, -7.25]);
+        assert!(matches!(
+            xor_float_decode::<f32>(&encoded[..encoded.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
     }
 }
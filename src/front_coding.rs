@@ -0,0 +1,343 @@
+// Front coding of a sorted set of strings.
+//
+// Each entry stores only how many leading bytes it shares with the previous
+// one and the remaining suffix, so sorted tag values like `us-east-1a`,
+// `us-east-1b` cost little more than their last byte. Every
+// `restart_interval`th entry is a restart point stored whole, which bounds
+// the entries decoded by a lookup and lets `find` binary search the restarts.
+//
+// Entry: varint shared prefix length | varint suffix length | suffix
+// Layout: varint entry count | varint restart interval | entries
+
+use std::{cmp::Ordering, hash::Hash};
+
+use crate::{
+    dict_encode::EncodingDict,
+    error::CodingError,
+    varint::{encode_u64, take_bytes, take_u64},
+};
+
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrontCoded {
+    len: usize,
+    restart_interval: usize,
+    // Offset in `data` of every restart point
+    restarts: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl FrontCoded {
+    // `sorted` must be strictly increasing, the position of a string is its code.
+    // A zero `restart_interval` is an error here and in `from_bytes`
+    pub fn new<S: AsRef<[u8]>>(sorted: &[S], restart_interval: usize) -> Result<Self, CodingError> {
+        if restart_interval == 0 {
+            return Err(CodingError::InvalidRestartInterval);
+        }
+        let mut restarts = Vec::new();
+        let mut data = Vec::new();
+        let mut prev: &[u8] = &[];
+        for (idx, value) in sorted.iter().enumerate() {
+            let value = value.as_ref();
+            if idx > 0 {
+                match prev.cmp(value) {
+                    Ordering::Less => (),
+                    Ordering::Equal => return Err(CodingError::DuplicateEntry),
+                    Ordering::Greater => return Err(CodingError::Unsorted),
+                }
+            }
+            let shared = if idx % restart_interval == 0 {
+                restarts.push(data.len());
+                0
+            } else {
+                shared_prefix(prev, value)
+            };
+            encode_u64(shared as u64, &mut data);
+            encode_u64((value.len() - shared) as u64, &mut data);
+            data.extend_from_slice(&value[shared..]);
+            prev = value;
+        }
+        Ok(Self {
+            len: sorted.len(),
+            restart_interval,
+            restarts,
+            data,
+        })
+    }
+
+    // Codes of a sorted dictionary carry over unchanged
+    pub fn from_dict<T>(
+        dict: &EncodingDict<T>,
+        restart_interval: usize,
+    ) -> Result<Self, CodingError>
+    where
+        T: Hash + Eq + Clone + AsRef<[u8]>,
+    {
+        if !dict.is_sorted() {
+            return Err(CodingError::Unsorted);
+        }
+        Self::new(dict.values(), restart_interval)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Encoded bytes of the entries
+    pub fn data_len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn get(&self, code: u64) -> Option<Vec<u8>> {
+        let code = usize::try_from(code).ok().filter(|x| *x < self.len)?;
+        let block = code / self.restart_interval;
+        let mut buff = &self.data[self.restarts[block]..];
+        let mut value = Vec::new();
+        for _ in block * self.restart_interval..=code {
+            // Entries were checked on construction
+            read_entry(&mut buff, &mut value).unwrap();
+        }
+        Some(value)
+    }
+
+    // Code of `value`, if it is in the set
+    pub fn find(&self, value: impl AsRef<[u8]>) -> Option<u64> {
+        let value = value.as_ref();
+        // Last restart whose entry is <= `value`
+        let block = self
+            .restarts
+            .partition_point(|offset| self.restart_value(*offset) <= value)
+            .checked_sub(1)?;
+        let mut buff = &self.data[self.restarts[block]..];
+        let mut current = Vec::new();
+        let start = block * self.restart_interval;
+        for code in start..self.len.min(start + self.restart_interval) {
+            read_entry(&mut buff, &mut current).unwrap();
+            match current.as_slice().cmp(value) {
+                Ordering::Less => (),
+                Ordering::Equal => return Some(code as u64),
+                Ordering::Greater => return None,
+            }
+        }
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        let mut buff = self.data.as_slice();
+        let mut value = Vec::new();
+        (0..self.len).map(move |_| {
+            read_entry(&mut buff, &mut value).unwrap();
+            value.clone()
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encode_u64(self.len as u64, &mut encoded);
+        encode_u64(self.restart_interval as u64, &mut encoded);
+        encoded.extend_from_slice(&self.data);
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let len = take_u64(&mut buff)? as usize;
+        let restart_interval = take_u64(&mut buff)? as usize;
+        if restart_interval == 0 {
+            return Err(CodingError::InvalidRestartInterval);
+        }
+        let start = buff;
+        let mut restarts = Vec::new();
+        let mut value = Vec::new();
+        let mut prev = Vec::new();
+        for idx in 0..len {
+            let offset = start.len() - buff.len();
+            let shared = read_entry(&mut buff, &mut value)?;
+            if idx % restart_interval == 0 {
+                if shared != 0 {
+                    return Err(CodingError::Malformed);
+                }
+                restarts.push(offset);
+            }
+            if idx > 0 && prev >= value {
+                return Err(CodingError::Unsorted);
+            }
+            prev.clone_from(&value);
+        }
+        if !buff.is_empty() {
+            return Err(CodingError::Malformed);
+        }
+        Ok(Self {
+            len,
+            restart_interval,
+            restarts,
+            data: start.to_vec(),
+        })
+    }
+
+    fn restart_value(&self, offset: usize) -> &[u8] {
+        let mut buff = &self.data[offset..];
+        let _shared = take_u64(&mut buff).unwrap();
+        let suffix_len = take_u64(&mut buff).unwrap() as usize;
+        &buff[..suffix_len]
+    }
+}
+
+// Replaces `value`, which holds the previous entry, with the next one,
+// returning its shared prefix length
+fn read_entry(data: &mut &[u8], value: &mut Vec<u8>) -> Result<usize, CodingError> {
+    let shared = take_u64(data)? as usize;
+    let suffix_len = take_u64(data)? as usize;
+    if shared > value.len() {
+        return Err(CodingError::Malformed);
+    }
+    value.truncate(shared);
+    value.extend_from_slice(take_bytes(data, suffix_len)?);
+    Ok(shared)
+}
+
+fn shared_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dict_encode::{dict_encode, sorted_dict_encode};
+
+    fn zones() -> Vec<String> {
+        let mut zones = Vec::new();
+        for region in [
+            "ap-south-1",
+            "eu-west-1",
+            "eu-west-2",
+            "us-east-1",
+            "us-west-2",
+        ] {
+            for zone in 'a'..='f' {
+                for rack in 0..10 {
+                    zones.push(format!("{region}{zone}-rack-{rack:02}"));
+                }
+            }
+        }
+        zones
+    }
+
+    #[test]
+    fn test_get() {
+        let data = zones();
+        let coded = FrontCoded::new(&data, DEFAULT_RESTART_INTERVAL).unwrap();
+        assert_eq!(coded.len(), data.len());
+        for (code, value) in data.iter().enumerate() {
+            assert_eq!(coded.get(code as u64).unwrap(), value.as_bytes());
+        }
+        assert_eq!(coded.get(data.len() as u64), None);
+        let all: Vec<Vec<u8>> = coded.iter().collect();
+        assert_eq!(all.len(), data.len());
+        assert_eq!(all[17], data[17].as_bytes());
+    }
+
+    #[test]
+    fn test_compresses() {
+        let data = zones();
+        let coded = FrontCoded::new(&data, DEFAULT_RESTART_INTERVAL).unwrap();
+        let raw_len: usize = data.iter().map(|x| x.len() + 1).sum();
+        assert!(coded.data_len() * 3 < raw_len);
+    }
+
+    #[test]
+    fn test_find() {
+        let data = zones();
+        for interval in [1, 4, DEFAULT_RESTART_INTERVAL, 1000] {
+            let coded = FrontCoded::new(&data, interval).unwrap();
+            for (code, value) in data.iter().enumerate() {
+                assert_eq!(coded.find(value), Some(code as u64));
+            }
+            for missing in ["", "aa", "eu-west-1a-rack-001", "us-east-1", "zz"] {
+                assert_eq!(coded.find(missing), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_prefix() {
+        let coded = FrontCoded::new(&["us-east-1a", "us-east-1b", "us-west-2"], 16).unwrap();
+        let expected = [
+            &[0, 10][..],
+            b"us-east-1a",
+            &[9, 1],
+            b"b",
+            &[3, 6],
+            b"west-2",
+        ]
+        .concat();
+        assert_eq!(coded.data, expected);
+    }
+
+    #[test]
+    fn test_from_dict() {
+        let data = zones();
+        let mut shuffled: Vec<String> = data.iter().rev().cloned().collect();
+        shuffled.extend(data.iter().step_by(3).cloned());
+        let (encoded, dict) = sorted_dict_encode(&shuffled);
+        let coded = FrontCoded::from_dict(&dict, DEFAULT_RESTART_INTERVAL).unwrap();
+        for (code, value) in encoded.iter().zip(&shuffled) {
+            assert_eq!(coded.get(*code).unwrap(), value.as_bytes());
+            assert_eq!(coded.find(value), Some(*code));
+        }
+
+        let (_, unsorted) = dict_encode(&shuffled);
+        assert!(matches!(
+            FrontCoded::from_dict(&unsorted, DEFAULT_RESTART_INTERVAL),
+            Err(CodingError::Unsorted)
+        ));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let coded = FrontCoded::new(&zones(), 8).unwrap();
+        let bytes = coded.to_bytes();
+        assert_eq!(FrontCoded::from_bytes(&bytes).unwrap(), coded);
+        assert!(matches!(
+            FrontCoded::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+        // Second entry claims a longer prefix than the first has
+        assert!(matches!(
+            FrontCoded::from_bytes(&[2, 16, 0, 1, b'a', 2, 1, b'b']),
+            Err(CodingError::Malformed)
+        ));
+        assert!(matches!(
+            FrontCoded::from_bytes(&[2, 16, 0, 1, b'b', 0, 1, b'a']),
+            Err(CodingError::Unsorted)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(matches!(
+            FrontCoded::new(&["b", "a"], 4),
+            Err(CodingError::Unsorted)
+        ));
+        assert!(matches!(
+            FrontCoded::new(&["a", "a"], 4),
+            Err(CodingError::DuplicateEntry)
+        ));
+        assert!(matches!(
+            FrontCoded::new(&["a"], 0),
+            Err(CodingError::InvalidRestartInterval)
+        ));
+        assert!(matches!(
+            FrontCoded::from_bytes(&[1, 0, 0, 1, b'a']),
+            Err(CodingError::InvalidRestartInterval)
+        ));
+        let empty = FrontCoded::new::<&str>(&[], 4).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.find("a"), None);
+    }
+}
@@ -0,0 +1,410 @@
+// FSST string compression (Boncz et al., VLDB 2020).
+//
+// A static table of up to 255 symbols, each 1 to 8 bytes, is trained on a
+// sample of the strings. Every string is then compressed on its own by
+// replacing the longest symbol at each position with its 1 byte code, bytes
+// no symbol covers are written as the escape code followed by the byte. As
+// strings are independent, a column of them keeps random access.
+//
+// Training follows the paper: starting from an empty table, each generation
+// compresses the sample, counts how often each symbol and each pair of
+// adjacent symbols occurs, and keeps the 255 candidates saving the most bytes.
+
+use std::collections::HashMap;
+
+use crate::{
+    dict_encode::EncodingDict,
+    error::CodingError,
+    varint::{encode_u64, take_bytes, take_u64, take_u8},
+};
+
+pub const MAX_SYMBOLS: usize = 255;
+pub const MAX_SYMBOL_LEN: usize = 8;
+// Code for a byte with no symbol, the byte itself follows
+pub const ESCAPE: u8 = 255;
+
+const GENERATIONS: usize = 5;
+// Bytes of strings to train on
+const SAMPLE_BYTES: usize = 16 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: Vec<Vec<u8>>,
+    // Codes of the symbols starting with each byte, longest first
+    by_first: Vec<Vec<u8>>,
+}
+
+impl SymbolTable {
+    fn new(mut symbols: Vec<Vec<u8>>) -> Self {
+        // Longest first so the greedy match takes the longest symbol
+        symbols.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        let mut by_first = vec![Vec::new(); 256];
+        for (code, symbol) in symbols.iter().enumerate() {
+            by_first[symbol[0] as usize].push(code as u8);
+        }
+        Self { symbols, by_first }
+    }
+
+    // Trains on an evenly spread sample of about `SAMPLE_BYTES` of `data`
+    pub fn train<S: AsRef<[u8]>>(data: &[S]) -> Self {
+        let total: usize = data.iter().map(|x| x.as_ref().len()).sum();
+        let step = (total / SAMPLE_BYTES).max(1);
+        let sample: Vec<&[u8]> = data.iter().step_by(step).map(|x| x.as_ref()).collect();
+
+        let mut table = Self::new(Vec::new());
+        for _ in 0..GENERATIONS {
+            // Bytes saved by each candidate: occurrences times length
+            let mut gains: HashMap<Vec<u8>, usize> = HashMap::new();
+            for string in &sample {
+                let mut pos = 0;
+                let mut prev: Option<&[u8]> = None;
+                while pos < string.len() {
+                    let unit = table.longest_match(&string[pos..]);
+                    *gains.entry(unit.to_vec()).or_default() += unit.len();
+                    if let Some(prev) = prev {
+                        let mut pair = prev.to_vec();
+                        pair.extend_from_slice(unit);
+                        pair.truncate(MAX_SYMBOL_LEN);
+                        let len = pair.len();
+                        *gains.entry(pair).or_default() += len;
+                    }
+                    prev = Some(unit);
+                    pos += unit.len();
+                }
+            }
+            let mut candidates: Vec<(Vec<u8>, usize)> = gains.into_iter().collect();
+            // Ties go to the smaller symbol so training is deterministic
+            candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            candidates.truncate(MAX_SYMBOLS);
+            table = Self::new(candidates.into_iter().map(|x| x.0).collect());
+        }
+        table
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::with_capacity(data.len());
+        let mut pos = 0;
+        while pos < data.len() {
+            match self.longest_code(&data[pos..]) {
+                Some(code) => {
+                    compressed.push(code);
+                    pos += self.symbols[code as usize].len();
+                }
+                None => {
+                    compressed.extend([ESCAPE, data[pos]]);
+                    pos += 1;
+                }
+            }
+        }
+        compressed
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CodingError> {
+        let mut decompressed = Vec::with_capacity(data.len() * 3);
+        let mut codes = data.iter();
+        while let Some(code) = codes.next() {
+            if *code == ESCAPE {
+                decompressed.push(*codes.next().ok_or(CodingError::Truncated)?);
+            } else {
+                let symbol = self
+                    .symbols
+                    .get(*code as usize)
+                    .ok_or(CodingError::MissingCode)?;
+                decompressed.extend_from_slice(symbol);
+            }
+        }
+        Ok(decompressed)
+    }
+
+    // Layout: symbol count (1 byte) | per symbol its length (1 byte) and bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoded = vec![self.symbols.len() as u8];
+        for symbol in &self.symbols {
+            encoded.push(symbol.len() as u8);
+            encoded.extend_from_slice(symbol);
+        }
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let table = Self::take(&mut buff)?;
+        if !buff.is_empty() {
+            return Err(CodingError::Malformed);
+        }
+        Ok(table)
+    }
+
+    fn take(data: &mut &[u8]) -> Result<Self, CodingError> {
+        let count = take_u8(data)? as usize;
+        let mut symbols = Vec::with_capacity(count);
+        for _ in 0..count {
+            let len = take_u8(data)? as usize;
+            if !(1..=MAX_SYMBOL_LEN).contains(&len) {
+                return Err(CodingError::Malformed);
+            }
+            symbols.push(take_bytes(data, len)?.to_vec());
+        }
+        let table = Self::new(symbols.clone());
+        // Codes are positions in the sorted order, so it must not change
+        if table.symbols != symbols {
+            return Err(CodingError::Malformed);
+        }
+        Ok(table)
+    }
+
+    fn longest_code(&self, data: &[u8]) -> Option<u8> {
+        self.by_first[data[0] as usize]
+            .iter()
+            .find(|code| data.starts_with(&self.symbols[**code as usize]))
+            .copied()
+    }
+
+    // The longest symbol starting `data`, or its first byte
+    fn longest_match<'a>(&'a self, data: &'a [u8]) -> &'a [u8] {
+        match self.longest_code(data) {
+            Some(code) => &self.symbols[code as usize],
+            None => &data[..1],
+        }
+    }
+}
+
+// Strings compressed with a shared symbol table, each one still readable on
+// its own
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsstColumn {
+    table: SymbolTable,
+    // Start of every string in `data`, plus the end
+    offsets: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl FsstColumn {
+    pub fn new<S: AsRef<[u8]>>(strings: &[S]) -> Self {
+        Self::with_table(SymbolTable::train(strings), strings)
+    }
+
+    pub fn with_table<S: AsRef<[u8]>>(table: SymbolTable, strings: &[S]) -> Self {
+        let mut offsets = Vec::with_capacity(strings.len() + 1);
+        let mut data = Vec::new();
+        offsets.push(0);
+        for string in strings {
+            data.extend(table.compress(string.as_ref()));
+            offsets.push(data.len());
+        }
+        Self {
+            table,
+            offsets,
+            data,
+        }
+    }
+
+    // The dictionary's values in code order, so `get(code)` decodes a code
+    pub fn from_dict<T>(dict: &EncodingDict<T>) -> Self
+    where
+        T: std::hash::Hash + Eq + Clone + AsRef<[u8]>,
+    {
+        Self::new(dict.values())
+    }
+
+    pub fn table(&self) -> &SymbolTable {
+        &self.table
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Compressed bytes of the strings, without the table
+    pub fn compressed_len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<Vec<u8>> {
+        if idx >= self.len() {
+            return None;
+        }
+        let compressed = &self.data[self.offsets[idx]..self.offsets[idx + 1]];
+        // Every string was checked on construction
+        Some(self.table.decompress(compressed).unwrap())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        (0..self.len()).map(|idx| self.get(idx).unwrap())
+    }
+
+    // Layout: symbol table | varint string count | varint compressed length
+    // of every string | compressed strings
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoded = self.table.to_bytes();
+        encode_u64(self.len() as u64, &mut encoded);
+        for idx in 0..self.len() {
+            encode_u64(
+                (self.offsets[idx + 1] - self.offsets[idx]) as u64,
+                &mut encoded,
+            );
+        }
+        encoded.extend_from_slice(&self.data);
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let table = SymbolTable::take(&mut buff)?;
+        let len = take_u64(&mut buff)?;
+        let mut offsets: Vec<usize> = vec![0];
+        for _ in 0..len {
+            let string_len = take_u64(&mut buff)? as usize;
+            let end = offsets
+                .last()
+                .unwrap()
+                .checked_add(string_len)
+                .ok_or(CodingError::Malformed)?;
+            if end > buff.len() {
+                return Err(CodingError::Truncated);
+            }
+            offsets.push(end);
+        }
+        if *offsets.last().unwrap() != buff.len() {
+            return Err(CodingError::Malformed);
+        }
+        for idx in 0..len as usize {
+            table.decompress(&buff[offsets[idx]..offsets[idx + 1]])?;
+        }
+        Ok(Self {
+            table,
+            offsets,
+            data: buff.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dict_encode::{decode_with_dict, dict_encode};
+
+    fn urls() -> Vec<String> {
+        (0..2000)
+            .map(|x| {
+                format!(
+                    "https://www.example.com/{}/item?id={}&ref=homepage",
+                    ["products", "blog", "search", "account"][x % 4],
+                    x * 7919 % 10007
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let data = urls();
+        let table = SymbolTable::train(&data);
+        assert!(table.len() <= MAX_SYMBOLS);
+        for string in &data {
+            let compressed = table.compress(string.as_bytes());
+            assert_eq!(table.decompress(&compressed).unwrap(), string.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_column_compresses() {
+        let data = urls();
+        let column = FsstColumn::new(&data);
+        let raw_len: usize = data.iter().map(|x| x.len()).sum();
+        assert!(column.compressed_len() * 3 < raw_len);
+        assert!(column.to_bytes().len() * 2 < raw_len);
+    }
+
+    #[test]
+    fn test_random_access() {
+        let data = urls();
+        let column = FsstColumn::new(&data);
+        assert_eq!(column.len(), data.len());
+        for idx in [0, 1, 999, 1999] {
+            assert_eq!(column.get(idx).unwrap(), data[idx].as_bytes());
+        }
+        assert_eq!(column.get(2000), None);
+    }
+
+    #[test]
+    fn test_escapes() {
+        let table = SymbolTable::train(&["aaaa"]);
+        // Bytes the table never saw, including the escape byte itself
+        let data = [0u8, 255, 128, 97, 97, 7];
+        let compressed = table.compress(&data);
+        assert_eq!(table.decompress(&compressed).unwrap(), data);
+
+        let empty = SymbolTable::train::<&[u8]>(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.compress(b"hi"), [ESCAPE, b'h', ESCAPE, b'i']);
+    }
+
+    #[test]
+    fn test_empty_strings() {
+        let data = vec!["", "x", ""];
+        let column = FsstColumn::new(&data);
+        let decoded: Vec<Vec<u8>> = column.iter().collect();
+        assert_eq!(decoded, vec![b"".to_vec(), b"x".to_vec(), b"".to_vec()]);
+    }
+
+    #[test]
+    fn test_from_dict() {
+        let data: Vec<String> = (0..3000)
+            .map(|x| format!("host-{}.us-east-1.internal", x % 300))
+            .collect();
+        let (encoded, dict) = dict_encode(&data);
+        let column = FsstColumn::from_dict(&dict);
+        assert_eq!(column.len(), 300);
+        let decoded = decode_with_dict(&encoded, &dict).unwrap();
+        for (code, value) in encoded.iter().zip(&decoded).take(500) {
+            assert_eq!(column.get(*code as usize).unwrap(), value.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let column = FsstColumn::new(&urls());
+        let bytes = column.to_bytes();
+        assert_eq!(FsstColumn::from_bytes(&bytes).unwrap(), column);
+        let table = column.table();
+        assert_eq!(&SymbolTable::from_bytes(&table.to_bytes()).unwrap(), table);
+
+        assert!(matches!(
+            FsstColumn::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+        assert!(matches!(
+            SymbolTable::from_bytes(&[1, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(CodingError::Malformed)
+        ));
+        // Lengths that add up past usize::MAX
+        let mut overflow = table.to_bytes();
+        encode_u64(2, &mut overflow);
+        encode_u64(1, &mut overflow);
+        encode_u64(u64::MAX, &mut overflow);
+        overflow.push(0);
+        assert!(matches!(
+            FsstColumn::from_bytes(&overflow),
+            Err(CodingError::Malformed)
+        ));
+        // Codes past the table
+        let table = SymbolTable::train(&["ab"]);
+        assert!(matches!(
+            table.decompress(&[200]),
+            Err(CodingError::MissingCode)
+        ));
+    }
+}
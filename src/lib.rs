@@ -1,15 +1,38 @@
+pub mod alp;
+pub mod analyzer;
+pub mod bitmap;
+pub mod bitpacking;
+pub mod boolean;
+pub mod chimp;
+pub mod decimal;
 pub mod delta_encode;
 pub mod dict_encode;
+pub mod elias_fano;
+pub mod error;
 pub mod float_xor;
+pub mod front_coding;
+pub mod fsst;
+pub mod lossy;
+pub mod nullable;
+pub mod pfor;
 pub mod rle;
+pub mod roaring;
+pub mod shuffle;
 pub mod simple8b;
+pub mod stats;
+pub mod stream_vbyte;
+pub mod varint;
 
 #[cfg(test)]
 mod tests {
     use self::{
-        delta_encode::{delta_delta_decode, delta_delta_encode},
+        bitpacking::{max_bit_width, pack, unpack},
+        delta_encode::{delta_decode, delta_delta_decode, delta_delta_encode, delta_encode},
+        dict_encode::{decode_with_dict, dict_encode},
         rle::{rle_decode, rle_encode},
+        shuffle::{shuffle, unshuffle},
         simple8b::{simple8b_decode, simple8b_encode},
+        varint::{decode_i64, decode_u64, encode_i64, encode_u64},
     };
 
     use super::*;
@@ -57,4 +80,58 @@ mod tests {
             .collect::<Vec<i32>>();
         assert_eq!(test_data, decompressed);
     }
+
+    #[test]
+    fn test_dict_with_bitpacking_round_trip() {
+        let test_data: Vec<String> = (0..500).map(|x| format!("host-{}", x % 11)).collect();
+        let (codes, dict) = dict_encode(&test_data);
+        let width = max_bit_width(&codes);
+        assert_eq!(width, 4);
+        let packed = pack(&codes, width);
+        assert_eq!(packed.len(), 250);
+        let unpacked = unpack(&packed, width, codes.len());
+        let decoded = decode_with_dict(&unpacked, &dict).expect("Should not have error in test");
+        assert_eq!(test_data, decoded);
+    }
+
+    #[test]
+    fn test_delta_with_varint_header_round_trip() {
+        let test_data: Vec<i64> = vec![-40, -38, -30, 15, 15, 16, 200];
+        let deltas = delta_encode(&test_data);
+        // Store the length and signed first value, the rest as packed deltas
+        let mut block = Vec::new();
+        encode_u64(deltas.len() as u64, &mut block);
+        encode_i64(deltas[0], &mut block);
+        let packed = simple8b_encode(&deltas[1..].iter().map(|x| *x as u64).collect::<Vec<u64>>());
+        assert_eq!(block, vec![7, 79]);
+
+        let (len, used) = decode_u64(&block).unwrap();
+        let (first, _) = decode_i64(&block[used..]).unwrap();
+        let mut decoded_deltas = vec![first];
+        decoded_deltas.extend(simple8b_decode(&packed).iter().map(|x| *x as i64));
+        assert_eq!(decoded_deltas.len(), len as usize);
+        assert_eq!(delta_decode(&decoded_deltas), test_data);
+    }
+
+    #[test]
+    fn test_shuffle_before_rle() {
+        let test_data: Vec<u8> = (0..1000u32)
+            .flat_map(|x| (100_000 + x).to_le_bytes())
+            .collect();
+        let runs = |bytes: &[u8]| {
+            let values: Vec<isize> = bytes.iter().map(|x| *x as isize).collect();
+            rle_encode(&values)
+        };
+        let shuffled = shuffle(&test_data, 4);
+        // The upper byte streams are constant so collapse to a few runs
+        assert!(runs(&shuffled).len() < runs(&test_data).len() / 3);
+
+        let rle: Vec<u64> = runs(&shuffled)
+            .iter()
+            .flat_map(|x| x.as_bytes())
+            .map(|x| x as u64)
+            .collect();
+        let decoded: Vec<u8> = rle_decode(&rle).iter().map(|x| *x as u8).collect();
+        assert_eq!(unshuffle(&decoded, 4), test_data);
+    }
 }
@@ -0,0 +1,216 @@
+// Error-bounded lossy compression of f64.
+//
+// Every value is quantized to the nearest multiple of 2 * epsilon above the
+// block minimum, so it decodes to within epsilon of the original. The
+// resulting integers are delta encoded, zigzag mapped and packed with
+// simple8b, so smooth series shrink to a few bits per point.
+//
+// Layout: varint value count | minimum (f64) | step (f64) | simple8b words
+
+use crate::{
+    delta_encode::{checked_delta_decode, delta_encode},
+    error::CodingError,
+    simple8b::{simple8b_from_bytes, simple8b_to_bytes},
+    varint::{encode_u64, take_bytes, take_u64, zigzag_decode, zigzag_encode},
+};
+
+// Largest value simple8b can pack
+const MAX_PACKED: u64 = (1 << 60) - 1;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorBound {
+    // Every decoded value is within this distance of the original
+    Absolute(f64),
+    // Bound as a fraction of the block's value range (max - min)
+    Relative(f64),
+}
+
+pub fn lossy_encode(data: &[f64], bound: ErrorBound) -> Result<Vec<u8>, CodingError> {
+    if data.iter().any(|x| !x.is_finite()) {
+        return Err(CodingError::InvalidErrorBound);
+    }
+    let min = data.iter().copied().reduce(f64::min).unwrap_or(0.0);
+    let max = data.iter().copied().reduce(f64::max).unwrap_or(0.0);
+    let epsilon = match bound {
+        ErrorBound::Absolute(epsilon) => epsilon,
+        ErrorBound::Relative(ratio) if ratio > 0.0 => ratio * (max - min),
+        ErrorBound::Relative(_) => -1.0,
+    };
+    // A constant block is exact whatever the step
+    let step = match epsilon {
+        x if x > 0.0 && x.is_finite() => 2.0 * x,
+        x if x == 0.0 && min == max => 1.0,
+        _ => return Err(CodingError::InvalidErrorBound),
+    };
+
+    let mut quantized = Vec::with_capacity(data.len());
+    for value in data {
+        quantized.push(quantize(*value, min, step, epsilon)?);
+    }
+    let zigzagged: Vec<u64> = delta_encode(&quantized)
+        .into_iter()
+        .map(zigzag_encode)
+        .collect();
+    if zigzagged.iter().any(|x| *x > MAX_PACKED) {
+        return Err(CodingError::InvalidErrorBound);
+    }
+
+    let mut encoded = Vec::new();
+    encode_u64(data.len() as u64, &mut encoded);
+    encoded.extend(min.to_le_bytes());
+    encoded.extend(step.to_le_bytes());
+    simple8b_to_bytes(&zigzagged, &mut encoded);
+    Ok(encoded)
+}
+
+pub fn lossy_decode(data: &[u8]) -> Result<Vec<f64>, CodingError> {
+    let mut buff = data;
+    let len = take_u64(&mut buff)? as usize;
+    let min = f64::from_le_bytes(take_bytes(&mut buff, 8)?.try_into().unwrap());
+    let step = f64::from_le_bytes(take_bytes(&mut buff, 8)?.try_into().unwrap());
+    let deltas: Vec<i64> = simple8b_from_bytes(buff, len)?
+        .into_iter()
+        .map(zigzag_decode)
+        .collect();
+    Ok(checked_delta_decode(&deltas)?
+        .into_iter()
+        .map(|x| dequantize(x, min, step))
+        .collect())
+}
+
+// Largest absolute difference between the original and decoded values
+pub fn max_abs_error(original: &[f64], decoded: &[f64]) -> f64 {
+    assert_eq!(original.len(), decoded.len(), "lengths differ");
+    original
+        .iter()
+        .zip(decoded)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f64::max)
+}
+
+fn dequantize(quantized: i64, min: f64, step: f64) -> f64 {
+    min + quantized as f64 * step
+}
+
+// Rounding in `dequantize` can push the nearest multiple just outside the
+// bound, so the neighbours are checked too
+fn quantize(value: f64, min: f64, step: f64, epsilon: f64) -> Result<i64, CodingError> {
+    let nearest = ((value - min) / step).round();
+    if nearest.abs() >= MAX_PACKED as f64 {
+        return Err(CodingError::InvalidErrorBound);
+    }
+    let nearest = nearest as i64;
+    [nearest, nearest - 1, nearest + 1]
+        .into_iter()
+        .find(|x| (dequantize(*x, min, step) - value).abs() <= epsilon.max(0.0))
+        .ok_or(CodingError::InvalidErrorBound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple8b::simple8b_encode;
+
+    fn sensor_data() -> Vec<f64> {
+        (0..5000)
+            .map(|x| 40.0 + (x as f64 * 0.01).sin() * 15.0 + (x as f64 * 0.7).cos() * 0.3)
+            .collect()
+    }
+
+    #[test]
+    fn test_absolute_bound() {
+        let data = sensor_data();
+        for epsilon in [0.5, 0.01, 1e-6] {
+            let encoded = lossy_encode(&data, ErrorBound::Absolute(epsilon)).unwrap();
+            let decoded = lossy_decode(&encoded).unwrap();
+            assert_eq!(decoded.len(), data.len());
+            assert!(max_abs_error(&data, &decoded) <= epsilon);
+        }
+    }
+
+    #[test]
+    fn test_compresses() {
+        let data = sensor_data();
+        let encoded = lossy_encode(&data, ErrorBound::Absolute(0.01)).unwrap();
+        assert!(encoded.len() < data.len() * 2);
+    }
+
+    #[test]
+    fn test_relative_bound() {
+        let data = sensor_data();
+        let encoded = lossy_encode(&data, ErrorBound::Relative(0.001)).unwrap();
+        let decoded = lossy_decode(&encoded).unwrap();
+        let min = data.iter().copied().reduce(f64::min).unwrap();
+        let max = data.iter().copied().reduce(f64::max).unwrap();
+        assert!(max_abs_error(&data, &decoded) <= 0.001 * (max - min));
+    }
+
+    #[test]
+    fn test_constant_block_is_exact() {
+        let data = vec![21.7; 100];
+        let encoded = lossy_encode(&data, ErrorBound::Relative(0.01)).unwrap();
+        assert_eq!(lossy_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_invalid_bounds() {
+        let data = sensor_data();
+        for bound in [
+            ErrorBound::Absolute(0.0),
+            ErrorBound::Absolute(-1.0),
+            ErrorBound::Absolute(f64::NAN),
+            ErrorBound::Relative(0.0),
+            // Too fine to quantize within simple8b's 60 bits
+            ErrorBound::Absolute(1e-300),
+        ] {
+            assert!(matches!(
+                lossy_encode(&data, bound),
+                Err(CodingError::InvalidErrorBound)
+            ));
+        }
+        assert!(matches!(
+            lossy_encode(&[1.0, f64::NAN], ErrorBound::Absolute(0.1)),
+            Err(CodingError::InvalidErrorBound)
+        ));
+    }
+
+    #[test]
+    fn test_max_abs_error() {
+        assert_eq!(max_abs_error(&[1.0, 2.0, 3.0], &[1.0, 2.25, 2.5]), 0.5);
+        assert_eq!(max_abs_error(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn test_empty() {
+        for bound in [ErrorBound::Absolute(0.1), ErrorBound::Relative(0.1)] {
+            let encoded = lossy_encode(&[], bound).unwrap();
+            assert!(lossy_decode(&encoded).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut header = Vec::new();
+        encode_u64(1, &mut header);
+        header.extend(0.0f64.to_le_bytes());
+        header.extend(1.0f64.to_le_bytes());
+        let zero_selector = [header.clone(), vec![0; 8]].concat();
+        assert!(matches!(
+            lossy_decode(&zero_selector),
+            Err(CodingError::Malformed)
+        ));
+
+        // Deltas that add up past i64::MAX
+        let mut overflow = Vec::new();
+        encode_u64(17, &mut overflow);
+        overflow.extend(0.0f64.to_le_bytes());
+        overflow.extend(1.0f64.to_le_bytes());
+        for word in simple8b_encode(&[zigzag_encode((1 << 59) - 1); 17]) {
+            overflow.extend(word.to_le_bytes());
+        }
+        assert!(matches!(
+            lossy_decode(&overflow),
+            Err(CodingError::Malformed)
+        ));
+    }
+}
@@ -0,0 +1,329 @@
+// Nullable columns: a series with gaps is split into a validity bitmap of the
+// rows holding a value and the dense non-null values, so the existing codecs
+// only ever see real points. Gaps tend to come in stretches, so validity is a
+// run optimized Roaring bitmap.
+//
+// Validity layout: varint row count | Roaring portable format of valid rows
+
+use std::{
+    hash::Hash,
+    ops::{Add, Sub},
+};
+
+use crate::{
+    bitmap::Bitmap,
+    delta_encode::{delta_decode, delta_encode},
+    dict_encode::{decode_with_dict, dict_encode, EncodingDict},
+    error::CodingError,
+    rle::{rle_decode, rle_encode, RunPair},
+    roaring::RoaringBitmap,
+    simple8b::{simple8b_decode, simple8b_encode},
+    varint::{encode_u64, take_u64},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Validity {
+    len: usize,
+    valid: RoaringBitmap,
+}
+
+impl Validity {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn valid_count(&self) -> usize {
+        self.valid.len() as usize
+    }
+
+    pub fn null_count(&self) -> usize {
+        self.len - self.valid_count()
+    }
+
+    pub fn is_valid(&self, row: usize) -> bool {
+        row < self.len && self.valid.contains(row as u32)
+    }
+
+    // Rows holding a value
+    pub fn valid(&self) -> &RoaringBitmap {
+        &self.valid
+    }
+
+    pub fn to_bitmap(&self) -> Bitmap {
+        self.valid.to_bitmap(self.len)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encode_u64(self.len as u64, &mut encoded);
+        encoded.extend(self.valid.to_bytes());
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let len = take_u64(&mut buff)?;
+        // Rows are addressed by u32, same limit as `split_nulls`
+        if len > 1 << 32 {
+            return Err(CodingError::Malformed);
+        }
+        let len = len as usize;
+        let valid = RoaringBitmap::from_bytes(buff)?;
+        if valid.iter().last().is_some_and(|x| x as usize >= len) {
+            return Err(CodingError::Malformed);
+        }
+        Ok(Self { len, valid })
+    }
+}
+
+pub fn split_nulls<T: Clone>(data: &[Option<T>]) -> (Validity, Vec<T>) {
+    assert!(
+        data.len() as u64 <= 1 << 32,
+        "too many rows for a validity bitmap"
+    );
+    let mut valid = RoaringBitmap::new();
+    let mut values = Vec::new();
+    for (row, item) in data.iter().enumerate() {
+        if let Some(value) = item {
+            valid.insert(row as u32);
+            values.push(value.clone());
+        }
+    }
+    valid.run_optimize();
+    let validity = Validity {
+        len: data.len(),
+        valid,
+    };
+    (validity, values)
+}
+
+// Inverse of `split_nulls`, `values` must hold one value per valid row
+pub fn join_nulls<T>(validity: &Validity, values: Vec<T>) -> Result<Vec<Option<T>>, CodingError> {
+    if values.len() != validity.valid_count() {
+        return Err(CodingError::Malformed);
+    }
+    let mut joined: Vec<Option<T>> = (0..validity.len).map(|_| None).collect();
+    for (row, value) in validity.valid.iter().zip(values) {
+        joined[row as usize] = Some(value);
+    }
+    Ok(joined)
+}
+
+pub fn nullable_rle_encode(data: &[Option<isize>]) -> (Validity, Vec<RunPair>) {
+    let (validity, values) = split_nulls(data);
+    if values.is_empty() {
+        return (validity, Vec::new());
+    }
+    (validity, rle_encode(&values))
+}
+
+pub fn nullable_rle_decode(
+    validity: &Validity,
+    data: &[u64],
+) -> Result<Vec<Option<isize>>, CodingError> {
+    join_nulls(validity, rle_decode(data))
+}
+
+// Deltas are taken between consecutive non-null values
+pub fn nullable_delta_encode<T>(data: &[Option<T>]) -> (Validity, Vec<T>)
+where
+    for<'a> &'a T: Sub<&'a T, Output = T>,
+    T: Clone,
+    T: Default,
+{
+    let (validity, values) = split_nulls(data);
+    (validity, delta_encode(&values))
+}
+
+pub fn nullable_delta_decode<T>(
+    validity: &Validity,
+    data: &[T],
+) -> Result<Vec<Option<T>>, CodingError>
+where
+    for<'a> &'a T: Add<&'a T, Output = T>,
+    T: Clone,
+    T: Default,
+{
+    if data.is_empty() {
+        return join_nulls(validity, Vec::new());
+    }
+    join_nulls(validity, delta_decode(data))
+}
+
+// Values must be below 2^60, the widest simple8b selector
+pub fn nullable_simple8b_encode(data: &[Option<u64>]) -> (Validity, Vec<u64>) {
+    let (validity, values) = split_nulls(data);
+    assert!(
+        values.iter().all(|x| *x < 1 << 60),
+        "value too large for simple8b"
+    );
+    (validity, simple8b_encode(&values))
+}
+
+pub fn nullable_simple8b_decode(
+    validity: &Validity,
+    data: &[u64],
+) -> Result<Vec<Option<u64>>, CodingError> {
+    // simple8b never writes the zero width selectors
+    if data.iter().any(|x| x >> 60 < 2) {
+        return Err(CodingError::Malformed);
+    }
+    join_nulls(validity, simple8b_decode(data))
+}
+
+// Nulls take no dictionary entry
+pub fn nullable_dict_encode<T>(data: &[Option<T>]) -> (Validity, Vec<u64>, EncodingDict<T>)
+where
+    T: Hash + Eq + Clone,
+{
+    let (validity, values) = split_nulls(data);
+    let (encoded, dict) = dict_encode(&values);
+    (validity, encoded, dict)
+}
+
+pub fn nullable_decode_with_dict<T>(
+    validity: &Validity,
+    data: &[u64],
+    dict: &EncodingDict<T>,
+) -> Result<Vec<Option<T>>, CodingError>
+where
+    T: Hash + Eq + Clone,
+{
+    join_nulls(validity, decode_with_dict(data, dict)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Gaps of varying length, including at both ends
+    fn with_gaps<T: Clone>(values: &[T]) -> Vec<Option<T>> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(idx, x)| match idx % 10 {
+                0 | 7 | 8 => None,
+                _ => Some(x.clone()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_join() {
+        let data = with_gaps(&(0..100).collect::<Vec<i64>>());
+        let (validity, values) = split_nulls(&data);
+        assert_eq!(validity.len(), 100);
+        assert_eq!(validity.null_count(), 30);
+        assert_eq!(values.len(), 70);
+        assert!(!validity.is_valid(0) && validity.is_valid(1) && !validity.is_valid(100));
+        assert_eq!(validity.to_bitmap().count_ones(), 70);
+        assert_eq!(join_nulls(&validity, values).unwrap(), data);
+        assert!(matches!(
+            join_nulls(&validity, vec![1i64; 69]),
+            Err(CodingError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_rle() {
+        let data = with_gaps(&[1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 4]);
+        let (validity, encoded) = nullable_rle_encode(&data);
+        let flat: Vec<u64> = encoded
+            .iter()
+            .flat_map(|x| x.as_bytes())
+            .map(|x| x as u64)
+            .collect();
+        assert_eq!(nullable_rle_decode(&validity, &flat).unwrap(), data);
+    }
+
+    #[test]
+    fn test_delta() {
+        let data = with_gaps(&(1000..1100).map(|x| x * 3).collect::<Vec<i64>>());
+        let (validity, encoded) = nullable_delta_encode(&data);
+        assert_eq!(encoded.len(), 70);
+        assert_eq!(nullable_delta_decode(&validity, &encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_simple8b() {
+        let data = with_gaps(&(0..500).map(|x| x % 17).collect::<Vec<u64>>());
+        let (validity, encoded) = nullable_simple8b_encode(&data);
+        assert_eq!(nullable_simple8b_decode(&validity, &encoded).unwrap(), data);
+        // One word too many
+        let mut extra = encoded.clone();
+        extra.push(encoded[0]);
+        assert!(matches!(
+            nullable_simple8b_decode(&validity, &extra),
+            Err(CodingError::Malformed)
+        ));
+        assert!(matches!(
+            nullable_simple8b_decode(&validity, &[0]),
+            Err(CodingError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_dict() {
+        let names = ["cpu", "mem", "disk"];
+        let data = with_gaps(&(0..60).map(|x| names[x % 3]).collect::<Vec<&str>>());
+        let (validity, encoded, dict) = nullable_dict_encode(&data);
+        assert_eq!(dict.len(), 3);
+        assert_eq!(
+            nullable_decode_with_dict(&validity, &encoded, &dict).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_all_or_no_nulls() {
+        let nulls: Vec<Option<isize>> = vec![None; 20];
+        let (validity, encoded) = nullable_rle_encode(&nulls);
+        assert!(encoded.is_empty());
+        assert_eq!(nullable_rle_decode(&validity, &[]).unwrap(), nulls);
+        let (validity, encoded) = nullable_delta_encode(&nulls);
+        assert_eq!(nullable_delta_decode(&validity, &encoded).unwrap(), nulls);
+
+        let dense: Vec<Option<u64>> = (0..20).map(Some).collect();
+        let (validity, encoded) = nullable_simple8b_encode(&dense);
+        assert_eq!(validity.null_count(), 0);
+        assert_eq!(
+            nullable_simple8b_decode(&validity, &encoded).unwrap(),
+            dense
+        );
+    }
+
+    #[test]
+    fn test_validity_bytes() {
+        let data = with_gaps(&vec![0u8; 100_000]);
+        let (validity, _) = split_nulls(&data);
+        let bytes = validity.to_bytes();
+        assert_eq!(Validity::from_bytes(&bytes).unwrap(), validity);
+
+        // A valid row past the end
+        let mut bad = Vec::new();
+        encode_u64(3, &mut bad);
+        bad.extend([3u32].into_iter().collect::<RoaringBitmap>().to_bytes());
+        assert!(matches!(
+            Validity::from_bytes(&bad),
+            Err(CodingError::Malformed)
+        ));
+        // More rows than a u32 can address
+        let mut bad = Vec::new();
+        encode_u64(1 << 62, &mut bad);
+        bad.extend(RoaringBitmap::new().to_bytes());
+        assert!(matches!(
+            Validity::from_bytes(&bad),
+            Err(CodingError::Malformed)
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "value too large for simple8b")]
+    fn test_simple8b_too_large() {
+        nullable_simple8b_encode(&[Some(1), None, Some(1 << 60)]);
+    }
+}
@@ -0,0 +1,193 @@
+// Patched frame-of-reference (PFOR) in the style of FastPFOR.
+//
+// Values are split into blocks of `BLOCK_SIZE`. Each block picks a bit width
+// `b` wide enough for at least 90% of its values; the low `b` bits of every
+// value are packed and the values that don't fit are stored as exceptions
+// (their position in the block plus the bits above `b`), which get patched
+// back in after unpacking.
+//
+// Block layout, all fields bytes:
+//   len | b | exception count | exception width
+//   packed low bits (ceil(len * b / 8) bytes)
+//   exception positions (one byte each)
+//   packed exception high bits (ceil(count * exception width / 8) bytes)
+
+use crate::{
+    bitpacking::{bit_width, low_mask, max_bit_width, pack_into, packed_len, unpack},
+    error::CodingError,
+    varint::{take_bytes, take_u8},
+};
+
+pub const BLOCK_SIZE: usize = 128;
+
+// Fraction of a block that must fit in the chosen width without patching
+const MIN_COVERAGE: f64 = 0.9;
+// Cost of an exception on top of its high bits, the one byte position
+const EXCEPTION_OVERHEAD_BITS: usize = 8;
+
+pub fn pfor_encode(data: &[u64]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for block in data.chunks(BLOCK_SIZE) {
+        encode_block(block, &mut encoded);
+    }
+    encoded
+}
+
+pub fn pfor_decode(data: &[u8]) -> Result<Vec<u64>, CodingError> {
+    let mut decoded = Vec::new();
+    let mut buff = data;
+    while !buff.is_empty() {
+        decode_block(&mut buff, &mut decoded)?;
+    }
+    Ok(decoded)
+}
+
+// Pick the width that minimises the block's size out of those covering at
+// least `MIN_COVERAGE` of the values
+fn choose_width(block: &[u64]) -> u8 {
+    let mut widths: Vec<u8> = block.iter().map(|x| bit_width(*x)).collect();
+    widths.sort_unstable();
+    let max_width = *widths.last().unwrap();
+    let covered = ((block.len() as f64 * MIN_COVERAGE).ceil() as usize).max(1);
+    let min_width = widths[covered - 1];
+
+    let mut best_width = max_width;
+    let mut best_cost = block.len() * max_width as usize;
+    for width in min_width..max_width {
+        let exceptions = widths.iter().filter(|w| **w > width).count();
+        let cost = block.len() * width as usize
+            + exceptions * (EXCEPTION_OVERHEAD_BITS + (max_width - width) as usize);
+        if cost < best_cost {
+            best_cost = cost;
+            best_width = width;
+        }
+    }
+    best_width
+}
+
+fn encode_block(block: &[u64], encoded: &mut Vec<u8>) {
+    let width = choose_width(block);
+    let mask = low_mask(width);
+
+    let mut positions = Vec::new();
+    let mut high_bits = Vec::new();
+    for (idx, value) in block.iter().enumerate() {
+        if bit_width(*value) > width {
+            positions.push(idx as u8);
+            high_bits.push(value >> width);
+        }
+    }
+    let exception_width = max_bit_width(&high_bits);
+
+    encoded.push(block.len() as u8);
+    encoded.push(width);
+    encoded.push(positions.len() as u8);
+    encoded.push(exception_width);
+    let low_bits: Vec<u64> = block.iter().map(|x| x & mask).collect();
+    pack_into(&low_bits, width, encoded);
+    encoded.extend(&positions);
+    pack_into(&high_bits, exception_width, encoded);
+}
+
+// Decodes the block at the front of `data` and advances past it
+fn decode_block(data: &mut &[u8], decoded: &mut Vec<u64>) -> Result<(), CodingError> {
+    let len = take_u8(data)? as usize;
+    let width = take_u8(data)?;
+    let num_exceptions = take_u8(data)? as usize;
+    let exception_width = take_u8(data)?;
+    if len == 0 || len > BLOCK_SIZE || width > 64 || num_exceptions > len {
+        return Err(CodingError::Malformed);
+    }
+    // Exceptions carry at least one high bit, which must fit above `width`
+    if num_exceptions > 0 && (exception_width == 0 || width as u32 + exception_width as u32 > 64) {
+        return Err(CodingError::Malformed);
+    }
+
+    let start = decoded.len();
+    let low_bits = take_bytes(data, packed_len(len, width))?;
+    decoded.extend(unpack(low_bits, width, len));
+
+    let positions = take_bytes(data, num_exceptions)?;
+    if positions.iter().any(|x| *x as usize >= len) || positions.windows(2).any(|x| x[0] >= x[1]) {
+        return Err(CodingError::Malformed);
+    }
+    let high_len = packed_len(num_exceptions, exception_width);
+    let high_bits = unpack(take_bytes(data, high_len)?, exception_width, num_exceptions);
+
+    // Patch the exceptions back over the truncated values
+    for (position, high) in positions.iter().zip(high_bits) {
+        decoded[start + *position as usize] |= high << width;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_width_ignores_outlier() {
+        let mut block = vec![3; BLOCK_SIZE];
+        block[17] = 1 << 40;
+        assert_eq!(choose_width(&block), 2);
+    }
+
+    #[test]
+    fn test_choose_width_uniform() {
+        let block: Vec<u64> = (0..BLOCK_SIZE as u64).collect();
+        assert_eq!(choose_width(&block), 7);
+    }
+
+    #[test]
+    fn test_outlier_is_cheap() {
+        let mut data = vec![5; BLOCK_SIZE];
+        data[64] = u64::MAX;
+        let encoded = pfor_encode(&data);
+        // 4 byte header, 3 bits per value, one position and 61 high bits
+        assert_eq!(encoded.len(), 4 + 48 + 1 + 8);
+        assert_eq!(pfor_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u64> = (0..1000u64)
+            .map(|x| if x % 97 == 0 { x * 1_000_003 } else { x % 13 })
+            .collect();
+        let encoded = pfor_encode(&data);
+        assert_eq!(pfor_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_round_trip_zeros_and_empty() {
+        let data = vec![0; 300];
+        assert_eq!(pfor_decode(&pfor_encode(&data)).unwrap(), data);
+        assert!(pfor_decode(&pfor_encode(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut data = vec![5; BLOCK_SIZE];
+        data[64] = u64::MAX;
+        let encoded = pfor_encode(&data);
+        assert!(matches!(
+            pfor_decode(&encoded[..encoded.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+        assert!(matches!(pfor_decode(&[1, 0]), Err(CodingError::Truncated)));
+        // Longer than a block
+        assert!(matches!(
+            pfor_decode(&[200, 0, 0, 0]),
+            Err(CodingError::Malformed)
+        ));
+        // Exception position past the end of the block
+        assert!(matches!(
+            pfor_decode(&[1, 1, 1, 1, 0, 5, 1]),
+            Err(CodingError::Malformed)
+        ));
+        // High bits that don't fit above a 64 bit width
+        assert!(matches!(
+            pfor_decode(&[1, 64, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+            Err(CodingError::Malformed)
+        ));
+    }
+}
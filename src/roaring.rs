@@ -0,0 +1,714 @@
+// Roaring bitmap (Chambi et al. 2016) for sets of u32 row positions.
+//
+// Values are split by their high 16 bits into chunks of 65536, each held in
+// whichever container suits it: a sorted array of the low 16 bits for up to
+// 4096 values, a 65536 bit bitmap for denser chunks, or a list of runs after
+// `run_optimize` for long stretches of consecutive values.
+//
+// `to_bytes` writes the Roaring portable format shared by the Java, C and Go
+// implementations, so bitmaps can be exchanged with them:
+//   cookie | (run flags) | key and cardinality - 1 per container | (offsets) |
+//   containers
+// All integers are little endian. Without run containers the cookie is
+// 12346 (u32) followed by the container count (u32) and offsets are always
+// present. With runs it is 12347 (u16) then the count - 1 (u16), a bitset
+// marking the run containers, and offsets only for 4 or more containers.
+
+use std::collections::BTreeSet;
+
+use crate::{bitmap::Bitmap, error::CodingError, varint::take_bytes};
+
+const ARRAY_MAX: usize = 4096;
+const BITMAP_WORDS: usize = 1024;
+const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+const SERIAL_COOKIE: u16 = 12347;
+// Run container files only have offsets from this many containers
+const NO_OFFSET_THRESHOLD: usize = 4;
+
+#[derive(Clone, Debug)]
+enum Container {
+    // Sorted low bits, at most `ARRAY_MAX` of them
+    Array(Vec<u16>),
+    // One bit per low 16 bit value
+    Bitmap(Box<[u64; BITMAP_WORDS]>),
+    // Sorted (start, length - 1) runs that neither overlap nor touch
+    Run(Vec<(u16, u16)>),
+}
+
+impl Container {
+    fn cardinality(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap(words) => words.iter().map(|x| x.count_ones() as usize).sum(),
+            Container::Run(runs) => runs.iter().map(|x| x.1 as usize + 1).sum(),
+        }
+    }
+
+    fn contains(&self, value: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&value).is_ok(),
+            Container::Bitmap(words) => words[value as usize / 64] & (1 << (value % 64)) != 0,
+            Container::Run(runs) => {
+                let idx = runs.partition_point(|x| x.0 <= value);
+                idx > 0 && value - runs[idx - 1].0 <= runs[idx - 1].1
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Container::Array(values) => Box::new(values.iter().copied()),
+            Container::Bitmap(words) => {
+                Box::new(words.iter().enumerate().flat_map(|(idx, word)| {
+                    let mut word = *word;
+                    std::iter::from_fn(move || {
+                        if word == 0 {
+                            return None;
+                        }
+                        let bit = word.trailing_zeros() as usize;
+                        word &= word - 1;
+                        Some((idx * 64 + bit) as u16)
+                    })
+                }))
+            }
+            Container::Run(runs) => {
+                Box::new(runs.iter().flat_map(|(start, len)| *start..=*start + *len))
+            }
+        }
+    }
+
+    fn to_words(&self) -> Box<[u64; BITMAP_WORDS]> {
+        if let Container::Bitmap(words) = self {
+            return words.clone();
+        }
+        let mut words = Box::new([0; BITMAP_WORDS]);
+        for value in self.iter() {
+            words[value as usize / 64] |= 1 << (value % 64);
+        }
+        words
+    }
+
+    // The smaller of array and bitmap for these bits, None when empty
+    fn from_words(words: Box<[u64; BITMAP_WORDS]>) -> Option<Container> {
+        let cardinality: usize = words.iter().map(|x| x.count_ones() as usize).sum();
+        match cardinality {
+            0 => None,
+            1..=ARRAY_MAX => Some(Container::Array(Container::Bitmap(words).iter().collect())),
+            _ => Some(Container::Bitmap(words)),
+        }
+    }
+
+    fn insert(&mut self, value: u16) -> bool {
+        match self {
+            Container::Array(values) => {
+                let Err(idx) = values.binary_search(&value) else {
+                    return false;
+                };
+                values.insert(idx, value);
+                if values.len() > ARRAY_MAX {
+                    *self = Container::Bitmap(self.to_words());
+                }
+                true
+            }
+            Container::Bitmap(words) => {
+                let (word, bit) = (value as usize / 64, 1 << (value % 64));
+                let added = words[word] & bit == 0;
+                words[word] |= bit;
+                added
+            }
+            Container::Run(_) => {
+                if self.contains(value) {
+                    return false;
+                }
+                let mut words = self.to_words();
+                words[value as usize / 64] |= 1 << (value % 64);
+                *self = Container::from_words(words).unwrap();
+                true
+            }
+        }
+    }
+
+    // None once the container is empty
+    fn remove(&mut self, value: u16) -> Option<bool> {
+        if !self.contains(value) {
+            return Some(false);
+        }
+        match self {
+            Container::Array(values) => {
+                values.retain(|x| *x != value);
+                (!values.is_empty()).then_some(true)
+            }
+            _ => {
+                let mut words = self.to_words();
+                words[value as usize / 64] &= !(1 << (value % 64));
+                *self = Container::from_words(words)?;
+                Some(true)
+            }
+        }
+    }
+
+    fn runs(&self) -> Vec<(u16, u16)> {
+        if let Container::Run(runs) = self {
+            return runs.clone();
+        }
+        let mut runs: Vec<(u16, u16)> = Vec::new();
+        for value in self.iter() {
+            match runs.last_mut() {
+                Some((start, len)) if *start as u32 + *len as u32 + 1 == value as u32 => *len += 1,
+                _ => runs.push((value, 0)),
+            }
+        }
+        runs
+    }
+
+    // Bytes the container takes in the portable format
+    fn serialized_len(&self) -> usize {
+        match self {
+            Container::Array(values) => 2 * values.len(),
+            Container::Bitmap(_) => 8 * BITMAP_WORDS,
+            Container::Run(runs) => 2 + 4 * runs.len(),
+        }
+    }
+
+    fn run_optimize(&mut self) {
+        let runs = Container::Run(self.runs());
+        let cardinality = self.cardinality();
+        let plain_len = if cardinality <= ARRAY_MAX {
+            2 * cardinality
+        } else {
+            8 * BITMAP_WORDS
+        };
+        if runs.serialized_len() < plain_len {
+            *self = runs;
+        } else if let Container::Run(_) = self {
+            *self = Container::from_words(self.to_words()).unwrap();
+        }
+    }
+
+    fn and(&self, other: &Container) -> Option<Container> {
+        match (self, other) {
+            (Container::Array(values), other) | (other, Container::Array(values)) => {
+                let values: Vec<u16> = values
+                    .iter()
+                    .copied()
+                    .filter(|x| other.contains(*x))
+                    .collect();
+                (!values.is_empty()).then_some(Container::Array(values))
+            }
+            _ => {
+                let mut words = self.to_words();
+                for (word, other) in words.iter_mut().zip(other.to_words().iter()) {
+                    *word &= other;
+                }
+                Container::from_words(words)
+            }
+        }
+    }
+
+    fn or(&self, other: &Container) -> Container {
+        if let (Container::Array(a), Container::Array(b)) = (self, other) {
+            if a.len() + b.len() <= ARRAY_MAX {
+                let values: BTreeSet<u16> = a.iter().chain(b).copied().collect();
+                return Container::Array(values.into_iter().collect());
+            }
+        }
+        let mut words = self.to_words();
+        for (word, other) in words.iter_mut().zip(other.to_words().iter()) {
+            *word |= other;
+        }
+        Container::from_words(words).unwrap()
+    }
+
+    fn and_not(&self, other: &Container) -> Option<Container> {
+        if let Container::Array(values) = self {
+            let values: Vec<u16> = values
+                .iter()
+                .copied()
+                .filter(|x| !other.contains(*x))
+                .collect();
+            return (!values.is_empty()).then_some(Container::Array(values));
+        }
+        let mut words = self.to_words();
+        for (word, other) in words.iter_mut().zip(other.to_words().iter()) {
+            *word &= !other;
+        }
+        Container::from_words(words)
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Container::Array(values) => {
+                for value in values {
+                    out.extend(value.to_le_bytes());
+                }
+            }
+            Container::Bitmap(words) => {
+                for word in words.iter() {
+                    out.extend(word.to_le_bytes());
+                }
+            }
+            Container::Run(runs) => {
+                out.extend((runs.len() as u16).to_le_bytes());
+                for (start, len) in runs {
+                    out.extend(start.to_le_bytes());
+                    out.extend(len.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    fn read(data: &mut &[u8], cardinality: usize, is_run: bool) -> Result<Container, CodingError> {
+        let container = if is_run {
+            let count = take_u16(data)? as usize;
+            let mut runs: Vec<(u16, u16)> = Vec::with_capacity(count);
+            for _ in 0..count {
+                let (start, len) = (take_u16(data)?, take_u16(data)?);
+                if start as u32 + len as u32 > u16::MAX as u32 {
+                    return Err(CodingError::Malformed);
+                }
+                if let Some((prev_start, prev_len)) = runs.last() {
+                    if (*prev_start as u32 + *prev_len as u32 + 1) >= start as u32 {
+                        return Err(CodingError::Malformed);
+                    }
+                }
+                runs.push((start, len));
+            }
+            Container::Run(runs)
+        } else if cardinality <= ARRAY_MAX {
+            let values = (0..cardinality)
+                .map(|_| take_u16(data))
+                .collect::<Result<Vec<u16>, _>>()?;
+            if values.windows(2).any(|x| x[0] >= x[1]) {
+                return Err(CodingError::Malformed);
+            }
+            Container::Array(values)
+        } else {
+            let mut words = Box::new([0; BITMAP_WORDS]);
+            for word in words.iter_mut() {
+                *word = u64::from_le_bytes(take_bytes(data, 8)?.try_into().unwrap());
+            }
+            Container::Bitmap(words)
+        };
+        if container.cardinality() != cardinality {
+            return Err(CodingError::Malformed);
+        }
+        Ok(container)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RoaringBitmap {
+    // High 16 bits of the values in each container, increasing
+    keys: Vec<u16>,
+    containers: Vec<Container>,
+}
+
+impl RoaringBitmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Rows set in a selection bitmap
+    pub fn from_bitmap(bitmap: &Bitmap) -> Self {
+        bitmap.ones().map(|x| x as u32).collect()
+    }
+
+    // Selection bitmap over `len` rows, values from `len` on are dropped
+    pub fn to_bitmap(&self, len: usize) -> Bitmap {
+        let mut bitmap = Bitmap::new(len);
+        for value in self.iter().take_while(|x| (*x as usize) < len) {
+            bitmap.set(value as usize);
+        }
+        bitmap
+    }
+
+    pub fn len(&self) -> u64 {
+        self.containers.iter().map(|x| x.cardinality() as u64).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty()
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        match self.keys.binary_search(&high(value)) {
+            Ok(idx) => self.containers[idx].contains(value as u16),
+            Err(_) => false,
+        }
+    }
+
+    // Whether `value` was not in the set yet
+    pub fn insert(&mut self, value: u32) -> bool {
+        match self.keys.binary_search(&high(value)) {
+            Ok(idx) => self.containers[idx].insert(value as u16),
+            Err(idx) => {
+                self.keys.insert(idx, high(value));
+                self.containers
+                    .insert(idx, Container::Array(vec![value as u16]));
+                true
+            }
+        }
+    }
+
+    // Whether `value` was in the set
+    pub fn remove(&mut self, value: u32) -> bool {
+        let Ok(idx) = self.keys.binary_search(&high(value)) else {
+            return false;
+        };
+        match self.containers[idx].remove(value as u16) {
+            Some(removed) => removed,
+            None => {
+                self.keys.remove(idx);
+                self.containers.remove(idx);
+                true
+            }
+        }
+    }
+
+    // Values in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.keys
+            .iter()
+            .zip(&self.containers)
+            .flat_map(|(key, container)| {
+                container
+                    .iter()
+                    .map(move |x| (*key as u32) << 16 | x as u32)
+            })
+    }
+
+    // Switches containers to runs wherever that is smaller, and back
+    pub fn run_optimize(&mut self) {
+        for container in &mut self.containers {
+            container.run_optimize();
+        }
+    }
+
+    pub fn union(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        self.merge(other, |a, b| match (a, b) {
+            (Some(a), Some(b)) => Some(a.or(b)),
+            (Some(x), None) | (None, Some(x)) => Some(x.clone()),
+            (None, None) => None,
+        })
+    }
+
+    pub fn intersection(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        self.merge(other, |a, b| a?.and(b?))
+    }
+
+    // Values in `self` but not in `other`
+    pub fn difference(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        self.merge(other, |a, b| match b {
+            Some(b) => a?.and_not(b),
+            None => a.cloned(),
+        })
+    }
+
+    // Combines the containers with matching keys
+    fn merge(
+        &self,
+        other: &RoaringBitmap,
+        op: impl Fn(Option<&Container>, Option<&Container>) -> Option<Container>,
+    ) -> RoaringBitmap {
+        let mut merged = RoaringBitmap::new();
+        let (mut a, mut b) = (0, 0);
+        while a < self.keys.len() || b < other.keys.len() {
+            let key_a = self.keys.get(a).copied().unwrap_or(u16::MAX);
+            let key_b = other.keys.get(b).copied().unwrap_or(u16::MAX);
+            let (key, container) =
+                if a < self.keys.len() && (b >= other.keys.len() || key_a < key_b) {
+                    a += 1;
+                    (key_a, op(Some(&self.containers[a - 1]), None))
+                } else if b < other.keys.len() && (a >= self.keys.len() || key_b < key_a) {
+                    b += 1;
+                    (key_b, op(None, Some(&other.containers[b - 1])))
+                } else {
+                    a += 1;
+                    b += 1;
+                    (
+                        key_a,
+                        op(
+                            Some(&self.containers[a - 1]),
+                            Some(&other.containers[b - 1]),
+                        ),
+                    )
+                };
+            if let Some(container) = container {
+                merged.keys.push(key);
+                merged.containers.push(container);
+            }
+        }
+        merged
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = self.containers.len();
+        let is_run: Vec<bool> = self
+            .containers
+            .iter()
+            .map(|x| matches!(x, Container::Run(_)))
+            .collect();
+        let has_runs = is_run.contains(&true);
+
+        let mut encoded = Vec::new();
+        if has_runs {
+            encoded.extend(SERIAL_COOKIE.to_le_bytes());
+            encoded.extend(((count - 1) as u16).to_le_bytes());
+            let mut flags = vec![0u8; count.div_ceil(8)];
+            for (idx, _) in is_run.iter().enumerate().filter(|x| *x.1) {
+                flags[idx / 8] |= 1 << (idx % 8);
+            }
+            encoded.extend(flags);
+        } else {
+            encoded.extend(SERIAL_COOKIE_NO_RUNCONTAINER.to_le_bytes());
+            encoded.extend((count as u32).to_le_bytes());
+        }
+        for (key, container) in self.keys.iter().zip(&self.containers) {
+            encoded.extend(key.to_le_bytes());
+            encoded.extend(((container.cardinality() - 1) as u16).to_le_bytes());
+        }
+        if !has_runs || count >= NO_OFFSET_THRESHOLD {
+            let mut offset = encoded.len() + 4 * count;
+            for container in &self.containers {
+                encoded.extend((offset as u32).to_le_bytes());
+                offset += container.serialized_len();
+            }
+        }
+        for container in &self.containers {
+            container.write(&mut encoded);
+        }
+        encoded
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CodingError> {
+        let mut buff = data;
+        let cookie = take_u32(&mut buff)?;
+        let (count, is_run, has_offsets) = if cookie == SERIAL_COOKIE_NO_RUNCONTAINER {
+            let count = take_u32(&mut buff)? as usize;
+            if count > 1 << 16 {
+                return Err(CodingError::Malformed);
+            }
+            (count, vec![false; count], true)
+        } else if cookie as u16 == SERIAL_COOKIE {
+            let count = (cookie >> 16) as usize + 1;
+            let flags = take_bytes(&mut buff, count.div_ceil(8))?;
+            let is_run = (0..count)
+                .map(|idx| flags[idx / 8] & (1 << (idx % 8)) != 0)
+                .collect();
+            (count, is_run, count >= NO_OFFSET_THRESHOLD)
+        } else {
+            return Err(CodingError::Malformed);
+        };
+
+        let mut keys = Vec::with_capacity(count);
+        let mut cardinalities = Vec::with_capacity(count);
+        for _ in 0..count {
+            keys.push(take_u16(&mut buff)?);
+            cardinalities.push(take_u16(&mut buff)? as usize + 1);
+        }
+        if keys.windows(2).any(|x| x[0] >= x[1]) {
+            return Err(CodingError::Malformed);
+        }
+        let offsets = if has_offsets {
+            (0..count)
+                .map(|_| take_u32(&mut buff).map(|x| x as usize))
+                .collect::<Result<Vec<usize>, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        let mut containers = Vec::with_capacity(count);
+        for idx in 0..count {
+            if has_offsets && offsets[idx] != data.len() - buff.len() {
+                return Err(CodingError::Malformed);
+            }
+            containers.push(Container::read(&mut buff, cardinalities[idx], is_run[idx])?);
+        }
+        if !buff.is_empty() {
+            return Err(CodingError::Malformed);
+        }
+        Ok(Self { keys, containers })
+    }
+}
+
+impl PartialEq for RoaringBitmap {
+    // Equal sets, however they are stored
+    fn eq(&self, other: &Self) -> bool {
+        self.keys == other.keys && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for RoaringBitmap {}
+
+impl FromIterator<u32> for RoaringBitmap {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let mut bitmap = RoaringBitmap::new();
+        for value in iter {
+            bitmap.insert(value);
+        }
+        bitmap
+    }
+}
+
+fn high(value: u32) -> u16 {
+    (value >> 16) as u16
+}
+
+fn take_u16(data: &mut &[u8]) -> Result<u16, CodingError> {
+    Ok(u16::from_le_bytes(take_bytes(data, 2)?.try_into().unwrap()))
+}
+
+fn take_u32(data: &mut &[u8]) -> Result<u32, CodingError> {
+    Ok(u32::from_le_bytes(take_bytes(data, 4)?.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sparse, dense and run-like chunks
+    fn test_values(seed: u64) -> BTreeSet<u32> {
+        let mut values = BTreeSet::new();
+        let mut state = seed;
+        for _ in 0..3000 {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            values.insert((state >> 40) as u32 % (4 << 16));
+        }
+        for idx in 0..10_000 {
+            values.insert((5 << 16) + (idx * (seed as u32 + 3)) % 65536);
+        }
+        values.extend((7 << 16) + 100 * seed as u32..(7 << 16) + 30_000);
+        values.insert(u32::MAX);
+        values
+    }
+
+    fn as_set(bitmap: &RoaringBitmap) -> BTreeSet<u32> {
+        bitmap.iter().collect()
+    }
+
+    #[test]
+    fn test_insert_contains_remove() {
+        let mut bitmap = RoaringBitmap::new();
+        assert!(bitmap.insert(7));
+        assert!(!bitmap.insert(7));
+        assert!(bitmap.insert(1 << 20));
+        assert!(bitmap.contains(7) && bitmap.contains(1 << 20));
+        assert!(!bitmap.contains(8));
+        assert_eq!(bitmap.len(), 2);
+        assert!(bitmap.remove(7));
+        assert!(!bitmap.remove(7));
+        assert!(bitmap.remove(1 << 20));
+        assert!(bitmap.is_empty());
+    }
+
+    #[test]
+    fn test_container_transitions() {
+        let mut bitmap: RoaringBitmap = (0..6000u32).map(|x| x * 3).chain([1 << 16]).collect();
+        assert!(matches!(bitmap.containers[0], Container::Bitmap(_)));
+        assert!(matches!(bitmap.containers[1], Container::Array(_)));
+        for value in (0..3000u32).map(|x| x * 6) {
+            assert!(bitmap.remove(value));
+        }
+        assert!(matches!(bitmap.containers[0], Container::Array(_)));
+        assert_eq!(bitmap.len(), 3001);
+
+        let mut runs: RoaringBitmap = (1000..60_000).collect();
+        runs.run_optimize();
+        assert!(matches!(runs.containers[0], Container::Run(_)));
+        assert!(runs.contains(1000) && runs.contains(59_999) && !runs.contains(999));
+        assert!(runs.insert(70));
+        assert!(runs.remove(30_000));
+        assert_eq!(runs.len(), 59_000);
+        runs.run_optimize();
+        assert!(matches!(runs.containers[0], Container::Run(_)));
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let (set_a, set_b) = (test_values(1), test_values(2));
+        let mut a: RoaringBitmap = set_a.iter().copied().collect();
+        let mut b: RoaringBitmap = set_b.iter().copied().collect();
+        for optimize in [false, true] {
+            if optimize {
+                a.run_optimize();
+                b.run_optimize();
+            }
+            assert_eq!(as_set(&a), set_a);
+            assert_eq!(as_set(&a.union(&b)), &set_a | &set_b);
+            assert_eq!(as_set(&a.intersection(&b)), &set_a & &set_b);
+            assert_eq!(as_set(&a.difference(&b)), &set_a - &set_b);
+            assert_eq!(as_set(&b.difference(&a)), &set_b - &set_a);
+            assert_eq!(a.intersection(&b).len(), (&set_a & &set_b).len() as u64);
+        }
+        assert!(a.difference(&a).is_empty());
+    }
+
+    #[test]
+    fn test_portable_format() {
+        let mut bitmap: RoaringBitmap = [1, 2, 3, 4].into_iter().collect();
+        let expected = [
+            0x3A, 0x30, 0, 0, 1, 0, 0, 0, 0, 0, 3, 0, 16, 0, 0, 0, 1, 0, 2, 0, 3, 0, 4, 0,
+        ];
+        assert_eq!(bitmap.to_bytes(), expected);
+
+        bitmap.run_optimize();
+        let expected = [0x3B, 0x30, 0, 0, 1, 0, 0, 3, 0, 1, 0, 1, 0, 3, 0];
+        assert_eq!(bitmap.to_bytes(), expected);
+        assert_eq!(RoaringBitmap::from_bytes(&expected).unwrap(), bitmap);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut bitmap: RoaringBitmap = test_values(3).into_iter().collect();
+        let plain = bitmap.to_bytes();
+        assert_eq!(RoaringBitmap::from_bytes(&plain).unwrap(), bitmap);
+        bitmap.run_optimize();
+        let with_runs = bitmap.to_bytes();
+        assert!(with_runs.len() < plain.len());
+        let decoded = RoaringBitmap::from_bytes(&with_runs).unwrap();
+        assert_eq!(decoded, bitmap);
+        assert_eq!(decoded.to_bytes(), with_runs);
+        assert_eq!(
+            RoaringBitmap::from_bytes(&RoaringBitmap::new().to_bytes()).unwrap(),
+            RoaringBitmap::new()
+        );
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let bytes = test_values(4)
+            .into_iter()
+            .collect::<RoaringBitmap>()
+            .to_bytes();
+        assert!(matches!(
+            RoaringBitmap::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CodingError::Truncated)
+        ));
+        assert!(matches!(
+            RoaringBitmap::from_bytes(&[0, 0, 0, 0]),
+            Err(CodingError::Malformed)
+        ));
+        // Array values out of order
+        let unsorted = [
+            0x3A, 0x30, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 16, 0, 0, 0, 2, 0, 1, 0,
+        ];
+        assert!(matches!(
+            RoaringBitmap::from_bytes(&unsorted),
+            Err(CodingError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_selection_bitmap() {
+        let mut selection = Bitmap::new(200);
+        for row in [0, 5, 64, 199] {
+            selection.set(row);
+        }
+        let bitmap = RoaringBitmap::from_bitmap(&selection);
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![0, 5, 64, 199]);
+        assert_eq!(bitmap.to_bitmap(200), selection);
+        assert_eq!(bitmap.to_bitmap(100).count_ones(), 3);
+    }
+}
@@ -0,0 +1,411 @@
+// Blosc style shuffle filters for arrays of 2, 4 or 8 byte elements.
+//
+// `shuffle` transposes the bytes of the elements, so the output holds the
+// first byte of every element, then every second byte, and so on.
+// `bitshuffle` goes further and transposes bits: bit plane `8 * j + k` holds
+// bit `k` of byte `j` of every element, packed 8 elements to a byte. Slowly
+// varying integers and floats then become long runs of equal bytes, which
+// RLE or any byte compressor handles far better than the raw array.
+//
+// Bytes past the last whole element (and, for `bitshuffle`, elements past
+// the last multiple of 8) are copied through unchanged at the end.
+
+pub fn shuffle(data: &[u8], width: usize) -> Vec<u8> {
+    check_width(width);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            let n = data.len() / width;
+            let mut shuffled = vec![0; data.len()];
+            // Safety: the CPU supports SSSE3 and `shuffled` is as long as `data`
+            unsafe { ssse3::shuffle(data, &mut shuffled, width, n) };
+            shuffled[n * width..].copy_from_slice(&data[n * width..]);
+            return shuffled;
+        }
+    }
+    shuffle_scalar(data, width)
+}
+
+pub fn shuffle_scalar(data: &[u8], width: usize) -> Vec<u8> {
+    check_width(width);
+    let n = data.len() / width;
+    let mut shuffled = vec![0; data.len()];
+    shuffle_tail(data, &mut shuffled, width, n, 0);
+    shuffled[n * width..].copy_from_slice(&data[n * width..]);
+    shuffled
+}
+
+pub fn unshuffle(data: &[u8], width: usize) -> Vec<u8> {
+    check_width(width);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            let n = data.len() / width;
+            let mut unshuffled = vec![0; data.len()];
+            // Safety: the CPU supports SSSE3 and `unshuffled` is as long as `data`
+            unsafe { ssse3::unshuffle(data, &mut unshuffled, width, n) };
+            unshuffled[n * width..].copy_from_slice(&data[n * width..]);
+            return unshuffled;
+        }
+    }
+    unshuffle_scalar(data, width)
+}
+
+pub fn unshuffle_scalar(data: &[u8], width: usize) -> Vec<u8> {
+    check_width(width);
+    let n = data.len() / width;
+    let mut unshuffled = vec![0; data.len()];
+    unshuffle_tail(data, &mut unshuffled, width, n, 0);
+    unshuffled[n * width..].copy_from_slice(&data[n * width..]);
+    unshuffled
+}
+
+pub fn bitshuffle(data: &[u8], width: usize) -> Vec<u8> {
+    check_width(width);
+    let n = bit_elements(data, width);
+    let streams = shuffle(&data[..n * width], width);
+    let mut shuffled = vec![0; data.len()];
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            // Safety: the CPU supports SSSE3 and there are `n` elements in
+            // each of the `width` streams
+            unsafe { ssse3::bit_transpose(&streams, &mut shuffled, width, n) };
+            shuffled[n * width..].copy_from_slice(&data[n * width..]);
+            return shuffled;
+        }
+    }
+    for j in 0..width {
+        bit_transpose_tail(&streams[j * n..], &mut shuffled, j, n, 0);
+    }
+    shuffled[n * width..].copy_from_slice(&data[n * width..]);
+    shuffled
+}
+
+pub fn bitshuffle_scalar(data: &[u8], width: usize) -> Vec<u8> {
+    check_width(width);
+    let n = bit_elements(data, width);
+    let streams = shuffle_scalar(&data[..n * width], width);
+    let mut shuffled = vec![0; data.len()];
+    for j in 0..width {
+        bit_transpose_tail(&streams[j * n..], &mut shuffled, j, n, 0);
+    }
+    shuffled[n * width..].copy_from_slice(&data[n * width..]);
+    shuffled
+}
+
+pub fn bitunshuffle(data: &[u8], width: usize) -> Vec<u8> {
+    check_width(width);
+    let n = bit_elements(data, width);
+    let plane_len = n / 8;
+    let mut streams = vec![0; n * width];
+    for j in 0..width {
+        for group in 0..plane_len {
+            let mut planes = [0; 8];
+            for (k, plane) in planes.iter_mut().enumerate() {
+                *plane = data[(8 * j + k) * plane_len + group];
+            }
+            let bytes = transpose8(u64::from_le_bytes(planes)).to_le_bytes();
+            let start = j * n + 8 * group;
+            streams[start..start + 8].copy_from_slice(&bytes);
+        }
+    }
+    let mut unshuffled = unshuffle(&streams, width);
+    unshuffled.extend_from_slice(&data[n * width..]);
+    unshuffled
+}
+
+fn check_width(width: usize) {
+    assert!(
+        matches!(width, 2 | 4 | 8),
+        "element width must be 2, 4 or 8"
+    );
+}
+
+// Elements covered by the bit transpose, a multiple of 8
+fn bit_elements(data: &[u8], width: usize) -> usize {
+    data.len() / width / 8 * 8
+}
+
+fn shuffle_tail(data: &[u8], shuffled: &mut [u8], width: usize, n: usize, start: usize) {
+    for i in start..n {
+        for j in 0..width {
+            shuffled[j * n + i] = data[i * width + j];
+        }
+    }
+}
+
+fn unshuffle_tail(data: &[u8], unshuffled: &mut [u8], width: usize, n: usize, start: usize) {
+    for i in start..n {
+        for j in 0..width {
+            unshuffled[i * width + j] = data[j * n + i];
+        }
+    }
+}
+
+// Bit planes of the `j`th byte stream, from element `start` on
+fn bit_transpose_tail(stream: &[u8], shuffled: &mut [u8], j: usize, n: usize, start: usize) {
+    let plane_len = n / 8;
+    for group in start / 8..plane_len {
+        let bytes = u64::from_le_bytes(stream[8 * group..8 * group + 8].try_into().unwrap());
+        for (k, plane) in transpose8(bytes).to_le_bytes().into_iter().enumerate() {
+            shuffled[(8 * j + k) * plane_len + group] = plane;
+        }
+    }
+}
+
+// Transposes the 8x8 bit matrix with bit `k` of byte `r` at `8 * r + k`
+// (Hacker's Delight 7-3), which is its own inverse
+fn transpose8(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00AA_00AA_00AA_00AA;
+    x ^= t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+    x ^= t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+    x ^ t ^ (t << 28)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod ssse3 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::{bit_transpose_tail, shuffle_tail, unshuffle_tail};
+
+    // For widths 2, 4 and 8, the `pshufb` mask grouping the bytes of the
+    // elements in a vector by their position, and its inverse
+    static GROUP_MASKS: [[u8; 16]; 3] = [group_mask(2), group_mask(4), group_mask(8)];
+    static UNGROUP_MASKS: [[u8; 16]; 3] = [ungroup_mask(2), ungroup_mask(4), ungroup_mask(8)];
+
+    const fn group_mask(width: usize) -> [u8; 16] {
+        let mut mask = [0; 16];
+        let lane = 16 / width;
+        let mut idx = 0;
+        while idx < 16 {
+            mask[(idx % width) * lane + idx / width] = idx as u8;
+            idx += 1;
+        }
+        mask
+    }
+
+    const fn ungroup_mask(width: usize) -> [u8; 16] {
+        let mut mask = [0; 16];
+        let lane = 16 / width;
+        let mut idx = 0;
+        while idx < 16 {
+            mask[idx] = ((idx % width) * lane + idx / width) as u8;
+            idx += 1;
+        }
+        mask
+    }
+
+    fn mask_idx(width: usize) -> usize {
+        width.trailing_zeros() as usize - 1
+    }
+
+    // Transposes a `width` x `width` matrix of `16 / width` byte lanes, so
+    // lane `m` of vector `j` swaps with lane `j` of vector `m`
+    #[target_feature(enable = "ssse3")]
+    unsafe fn transpose(v: &mut [__m128i]) {
+        match v.len() {
+            2 => {
+                let (a, b) = (v[0], v[1]);
+                v[0] = _mm_unpacklo_epi64(a, b);
+                v[1] = _mm_unpackhi_epi64(a, b);
+            }
+            4 => {
+                let t0 = _mm_unpacklo_epi32(v[0], v[1]);
+                let t1 = _mm_unpackhi_epi32(v[0], v[1]);
+                let t2 = _mm_unpacklo_epi32(v[2], v[3]);
+                let t3 = _mm_unpackhi_epi32(v[2], v[3]);
+                v[0] = _mm_unpacklo_epi64(t0, t2);
+                v[1] = _mm_unpackhi_epi64(t0, t2);
+                v[2] = _mm_unpacklo_epi64(t1, t3);
+                v[3] = _mm_unpackhi_epi64(t1, t3);
+            }
+            _ => {
+                let mut s = [_mm_setzero_si128(); 8];
+                for pair in 0..4 {
+                    s[2 * pair] = _mm_unpacklo_epi16(v[2 * pair], v[2 * pair + 1]);
+                    s[2 * pair + 1] = _mm_unpackhi_epi16(v[2 * pair], v[2 * pair + 1]);
+                }
+                let mut u = [_mm_setzero_si128(); 8];
+                for half in 0..2 {
+                    let (a, b, c, d) = (
+                        s[4 * half],
+                        s[4 * half + 1],
+                        s[4 * half + 2],
+                        s[4 * half + 3],
+                    );
+                    u[4 * half] = _mm_unpacklo_epi32(a, c);
+                    u[4 * half + 1] = _mm_unpackhi_epi32(a, c);
+                    u[4 * half + 2] = _mm_unpacklo_epi32(b, d);
+                    u[4 * half + 3] = _mm_unpackhi_epi32(b, d);
+                }
+                for idx in 0..4 {
+                    v[2 * idx] = _mm_unpacklo_epi64(u[idx], u[idx + 4]);
+                    v[2 * idx + 1] = _mm_unpackhi_epi64(u[idx], u[idx + 4]);
+                }
+            }
+        }
+    }
+
+    // Shuffles 16 elements per step, leaving the rest to the scalar code
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn shuffle(data: &[u8], shuffled: &mut [u8], width: usize, n: usize) {
+        let mask = _mm_loadu_si128(GROUP_MASKS[mask_idx(width)].as_ptr() as *const __m128i);
+        let mut v = [_mm_setzero_si128(); 8];
+        let mut i = 0;
+        while i + 16 <= n {
+            for (m, vector) in v[..width].iter_mut().enumerate() {
+                let input =
+                    _mm_loadu_si128(data.as_ptr().add(i * width + 16 * m) as *const __m128i);
+                *vector = _mm_shuffle_epi8(input, mask);
+            }
+            transpose(&mut v[..width]);
+            for (j, vector) in v[..width].iter().enumerate() {
+                _mm_storeu_si128(
+                    shuffled.as_mut_ptr().add(j * n + i) as *mut __m128i,
+                    *vector,
+                );
+            }
+            i += 16;
+        }
+        shuffle_tail(data, shuffled, width, n, i);
+    }
+
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn unshuffle(data: &[u8], unshuffled: &mut [u8], width: usize, n: usize) {
+        let mask = _mm_loadu_si128(UNGROUP_MASKS[mask_idx(width)].as_ptr() as *const __m128i);
+        let mut v = [_mm_setzero_si128(); 8];
+        let mut i = 0;
+        while i + 16 <= n {
+            for (j, vector) in v[..width].iter_mut().enumerate() {
+                *vector = _mm_loadu_si128(data.as_ptr().add(j * n + i) as *const __m128i);
+            }
+            transpose(&mut v[..width]);
+            for (m, vector) in v[..width].iter().enumerate() {
+                let output = unshuffled.as_mut_ptr().add(i * width + 16 * m);
+                _mm_storeu_si128(output as *mut __m128i, _mm_shuffle_epi8(*vector, mask));
+            }
+            i += 16;
+        }
+        unshuffle_tail(data, unshuffled, width, n, i);
+    }
+
+    // `movemask` collects the top bit of 16 bytes at once, shifting each
+    // byte left brings the next bit up
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn bit_transpose(
+        streams: &[u8],
+        shuffled: &mut [u8],
+        width: usize,
+        n: usize,
+    ) {
+        let plane_len = n / 8;
+        for j in 0..width {
+            let stream = &streams[j * n..(j + 1) * n];
+            let mut i = 0;
+            while i + 16 <= n {
+                let mut x = _mm_loadu_si128(stream.as_ptr().add(i) as *const __m128i);
+                for k in (0..8).rev() {
+                    let bits = (_mm_movemask_epi8(x) as u16).to_le_bytes();
+                    let at = (8 * j + k) * plane_len + i / 8;
+                    shuffled[at..at + 2].copy_from_slice(&bits);
+                    x = _mm_slli_epi16(x, 1);
+                }
+                i += 16;
+            }
+            bit_transpose_tail(stream, shuffled, j, n, i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_bytes(len: usize) -> Vec<u8> {
+        (0..len as u64)
+            .map(|x| (x.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 56) as u8)
+            .collect()
+    }
+
+    fn naive_bitshuffle(data: &[u8], width: usize) -> Vec<u8> {
+        let n = data.len() / width / 8 * 8;
+        let mut shuffled = vec![0; data.len()];
+        for plane in 0..8 * width {
+            for i in 0..n {
+                let bit = (data[i * width + plane / 8] >> (plane % 8)) & 1;
+                shuffled[plane * n / 8 + i / 8] |= bit << (i % 8);
+            }
+        }
+        shuffled[n * width..].copy_from_slice(&data[n * width..]);
+        shuffled
+    }
+
+    const LENGTHS: [usize; 9] = [0, 1, 7, 15, 16, 17, 33, 100, 1001];
+
+    #[test]
+    fn test_shuffle() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        assert_eq!(shuffle(&data, 2), [1, 3, 5, 7, 2, 4, 6, 8, 9]);
+        assert_eq!(shuffle(&data, 4), [1, 5, 2, 6, 3, 7, 4, 8, 9]);
+        assert_eq!(shuffle(&data, 8), data);
+    }
+
+    #[test]
+    fn test_shuffle_matches_scalar() {
+        for width in [2, 4, 8] {
+            for elements in LENGTHS {
+                let data = test_bytes(elements * width + width / 2);
+                let shuffled = shuffle(&data, width);
+                assert_eq!(shuffled, shuffle_scalar(&data, width));
+                assert_eq!(unshuffle(&shuffled, width), data);
+                assert_eq!(unshuffle_scalar(&shuffled, width), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transpose8() {
+        let x = 0x0123_4567_89AB_CDEF;
+        let transposed = transpose8(x);
+        for r in 0..8 {
+            for k in 0..8 {
+                assert_eq!((x >> (8 * r + k)) & 1, (transposed >> (8 * k + r)) & 1);
+            }
+        }
+        assert_eq!(transpose8(transposed), x);
+    }
+
+    #[test]
+    fn test_bitshuffle() {
+        for width in [2, 4, 8] {
+            for elements in LENGTHS {
+                let data = test_bytes(elements * width + 1);
+                let shuffled = bitshuffle(&data, width);
+                assert_eq!(shuffled, naive_bitshuffle(&data, width));
+                assert_eq!(bitshuffle_scalar(&data, width), shuffled);
+                assert_eq!(bitunshuffle(&shuffled, width), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitshuffle_small_values() {
+        // 16 u32s below 4 only use the two lowest bit planes
+        let data: Vec<u8> = (0..16u32).flat_map(|x| (x % 4).to_le_bytes()).collect();
+        let shuffled = bitshuffle(&data, 4);
+        assert_eq!(&shuffled[..4], [0xAA, 0xAA, 0xCC, 0xCC]);
+        assert!(shuffled[4..].iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "element width must be 2, 4 or 8")]
+    fn test_invalid_width() {
+        shuffle(&[1, 2, 3], 3);
+    }
+}
@@ -1,6 +1,8 @@
 use itertools::izip;
 use std::mem::size_of;
 
+use crate::error::CodingError;
+
 const SELECTOR_VALUE: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
 const INTEGERS_CODED: [u8; 16] = [240, 120, 60, 30, 20, 15, 12, 10, 8, 7, 6, 5, 4, 3, 2, 1];
 const BPI: [u8; 16] = [0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 12, 15, 20, 30, 60];
@@ -32,22 +34,45 @@ pub fn simple8b_decode(data: &[u64]) -> Vec<u64> {
     return decoded;
 }
 
-fn unpack(word: u64) -> Vec<u64> {
+// Appends the packed words of `data` as little endian bytes, the form the
+// lossy and decimal codecs store them in
+pub(crate) fn simple8b_to_bytes(data: &[u64], out: &mut Vec<u8>) {
+    for word in simple8b_encode(data) {
+        out.extend(word.to_le_bytes());
+    }
+}
+
+// Inverse of `simple8b_to_bytes` for `len` values, rejecting input it can't
+// have written
+pub(crate) fn simple8b_from_bytes(data: &[u8], len: usize) -> Result<Vec<u64>, CodingError> {
+    if !data.len().is_multiple_of(8) {
+        return Err(CodingError::Truncated);
+    }
+    let words: Vec<u64> = data
+        .chunks_exact(8)
+        .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+        .collect();
+    // The zero width selectors are never written
+    if words.iter().any(|x| x >> 60 < 2) {
+        return Err(CodingError::Malformed);
+    }
+    let decoded = simple8b_decode(&words);
+    if decoded.len() != len {
+        return Err(CodingError::Malformed);
+    }
+    Ok(decoded)
+}
+
+pub(crate) fn unpack(word: u64) -> Vec<u64> {
     let mut decoded_words: Vec<u64> = Vec::new();
     let selector = word >> 60;
     let bits_per_int = BPI[selector as usize];
     let encoded_words = INTEGERS_CODED[selector as usize];
-    println!(
-        "selector: {}, bpi {}, ew {}",
-        selector, bits_per_int, encoded_words
-    );
 
     let bitmask = (u64::MAX >> (64 - bits_per_int)) << (64 - bits_per_int);
-    println!("bitmask {:^0x}", bitmask);
 
     for w in 0..encoded_words {
         let temp_mask = bitmask >> (4 + (w * bits_per_int));
-        println!("temp mask {:^016x}", temp_mask);
         let x = (word & temp_mask) >> (60 - bits_per_int) - (bits_per_int * w);
         decoded_words.push(x);
     }
@@ -150,6 +175,26 @@ mod tests {
         assert_eq!(encoded, correct);
     }
 
+    #[test]
+    fn test_simple8b_bytes() {
+        let test_data = vec![3, 1 << 40, 7, 0, 12];
+        let mut bytes = Vec::new();
+        simple8b_to_bytes(&test_data, &mut bytes);
+        assert_eq!(simple8b_from_bytes(&bytes, 5).unwrap(), test_data);
+        assert!(matches!(
+            simple8b_from_bytes(&bytes, 4),
+            Err(CodingError::Malformed)
+        ));
+        assert!(matches!(
+            simple8b_from_bytes(&bytes[1..], 5),
+            Err(CodingError::Truncated)
+        ));
+        assert!(matches!(
+            simple8b_from_bytes(&[0; 8], 240),
+            Err(CodingError::Malformed)
+        ));
+    }
+
     #[test]
     fn test_simple8b_2_words() {
         let test_data = vec![1; 60 * 2];
@@ -0,0 +1,243 @@
+// Compression statistics for tuning schemas against real data. The
+// `*_with_stats` functions return the same output as the codec they wrap
+// together with a `CompressionStats` describing it.
+//
+// Codecs whose output isn't bytes are measured as they would be stored:
+// simple8b words at 8 bytes, RLE pairs as a zigzag varint value and a varint
+// run length, and dictionary codes bit packed at the width of the largest
+// code after the serialized dictionary.
+
+use std::fmt;
+
+use crate::{
+    bitpacking::{bit_width, packed_len},
+    dict_encode::{dict_encode, DictEntry, EncodingDict},
+    float_xor::{xor_float_encode_counted, FloatBits, XorControls},
+    rle::{rle_encode, RunPair},
+    simple8b::simple8b_encode,
+    varint::{encoded_len, zigzag_encode},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub values: usize,
+    pub input_bytes: usize,
+    pub output_bytes: usize,
+    pub breakdown: Breakdown,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Breakdown {
+    #[default]
+    None,
+    // Words written with each of the 16 selectors
+    Simple8b {
+        selectors: [usize; 16],
+    },
+    // Entry `i` counts the runs with a length in [2^i, 2^(i+1))
+    Rle {
+        run_lengths: Vec<usize>,
+    },
+    Dictionary {
+        cardinality: usize,
+        dict_bytes: usize,
+    },
+    Xor(XorControls),
+}
+
+impl CompressionStats {
+    pub fn new(values: usize, input_bytes: usize, output_bytes: usize) -> Self {
+        Self {
+            values,
+            input_bytes,
+            output_bytes,
+            breakdown: Breakdown::None,
+        }
+    }
+
+    // Input bytes per output byte, 1 when nothing was written
+    pub fn ratio(&self) -> f64 {
+        if self.output_bytes == 0 {
+            return 1.0;
+        }
+        self.input_bytes as f64 / self.output_bytes as f64
+    }
+
+    pub fn bits_per_value(&self) -> f64 {
+        if self.values == 0 {
+            return 0.0;
+        }
+        (8 * self.output_bytes) as f64 / self.values as f64
+    }
+}
+
+impl fmt::Display for CompressionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} values, {} -> {} bytes ({:.2}x, {:.2} bits/value)",
+            self.values,
+            self.input_bytes,
+            self.output_bytes,
+            self.ratio(),
+            self.bits_per_value()
+        )
+    }
+}
+
+pub fn simple8b_encode_with_stats(data: &[u64]) -> (Vec<u64>, CompressionStats) {
+    let encoded = simple8b_encode(data);
+    let mut selectors = [0; 16];
+    for word in &encoded {
+        selectors[(word >> 60) as usize] += 1;
+    }
+    let mut stats = CompressionStats::new(data.len(), 8 * data.len(), 8 * encoded.len());
+    stats.breakdown = Breakdown::Simple8b { selectors };
+    (encoded, stats)
+}
+
+pub fn rle_encode_with_stats(data: &[isize]) -> (Vec<RunPair>, CompressionStats) {
+    let input_bytes = size_of_val(data);
+    if data.is_empty() {
+        let mut stats = CompressionStats::new(0, 0, 0);
+        stats.breakdown = Breakdown::Rle {
+            run_lengths: Vec::new(),
+        };
+        return (Vec::new(), stats);
+    }
+    let encoded = rle_encode(data);
+    let mut run_lengths = Vec::new();
+    let mut output_bytes = 0;
+    for pair in &encoded {
+        let pair = pair.as_bytes();
+        output_bytes += encoded_len(zigzag_encode(pair[0] as i64)) + encoded_len(pair[1] as u64);
+        let bucket = pair[1].ilog2() as usize;
+        if run_lengths.len() <= bucket {
+            run_lengths.resize(bucket + 1, 0);
+        }
+        run_lengths[bucket] += 1;
+    }
+    let mut stats = CompressionStats::new(data.len(), input_bytes, output_bytes);
+    stats.breakdown = Breakdown::Rle { run_lengths };
+    (encoded, stats)
+}
+
+// Input is measured as each value's dictionary entry
+pub fn dict_encode_with_stats<T: DictEntry>(
+    data: &[T],
+) -> (Vec<u64>, EncodingDict<T>, CompressionStats) {
+    let (encoded, dict) = dict_encode(data);
+    let input_bytes = data.iter().map(|x| x.entry_len()).sum();
+    let dict_bytes = dict.to_bytes().len();
+    let width = bit_width(dict.len().saturating_sub(1) as u64);
+    let output_bytes = dict_bytes + packed_len(encoded.len(), width);
+    let mut stats = CompressionStats::new(data.len(), input_bytes, output_bytes);
+    stats.breakdown = Breakdown::Dictionary {
+        cardinality: dict.len(),
+        dict_bytes,
+    };
+    (encoded, dict, stats)
+}
+
+pub fn xor_float_encode_with_stats<F: FloatBits>(data: &[F]) -> (Vec<u8>, CompressionStats) {
+    let (encoded, controls) = xor_float_encode_counted(data);
+    let input_bytes = data.len() * F::BITS as usize / 8;
+    let mut stats = CompressionStats::new(data.len(), input_bytes, encoded.len());
+    stats.breakdown = Breakdown::Xor(controls);
+    (encoded, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float_xor::xor_float_encode;
+
+    #[test]
+    fn test_ratio() {
+        let stats = CompressionStats::new(100, 800, 200);
+        assert_eq!(stats.ratio(), 4.0);
+        assert_eq!(stats.bits_per_value(), 16.0);
+        assert_eq!(
+            stats.to_string(),
+            "100 values, 800 -> 200 bytes (4.00x, 16.00 bits/value)"
+        );
+        let empty = CompressionStats::new(0, 0, 0);
+        assert_eq!((empty.ratio(), empty.bits_per_value()), (1.0, 0.0));
+    }
+
+    #[test]
+    fn test_simple8b() {
+        // 60 values of 1 bit then one value needing 60 bits
+        let mut data = vec![1u64; 60];
+        data.push(1 << 59);
+        let (encoded, stats) = simple8b_encode_with_stats(&data);
+        assert_eq!(encoded, simple8b_encode(&data));
+        assert_eq!((stats.input_bytes, stats.output_bytes), (8 * 61, 16));
+        let mut selectors = [0; 16];
+        selectors[2] = 1;
+        selectors[15] = 1;
+        assert_eq!(stats.breakdown, Breakdown::Simple8b { selectors });
+    }
+
+    #[test]
+    fn test_rle() {
+        let data: Vec<isize> = [vec![1; 5], vec![-2; 1], vec![7; 40], vec![0; 3]].concat();
+        let (encoded, stats) = rle_encode_with_stats(&data);
+        assert_eq!(encoded, rle_encode(&data));
+        assert_eq!(stats.values, 49);
+        // Every value and run fits a single varint byte
+        assert_eq!(stats.output_bytes, 8);
+        assert_eq!(
+            stats.breakdown,
+            Breakdown::Rle {
+                run_lengths: vec![1, 1, 1, 0, 0, 1]
+            }
+        );
+        let (encoded, stats) = rle_encode_with_stats(&[]);
+        assert!(encoded.is_empty());
+        assert_eq!(stats.values, 0);
+    }
+
+    #[test]
+    fn test_dict() {
+        let hosts = ["web-01", "web-02", "db-01", "cache-01"];
+        let data: Vec<String> = (0..1000).map(|x| hosts[x % 4].to_string()).collect();
+        let (encoded, dict, stats) = dict_encode_with_stats(&data);
+        assert_eq!(encoded.len(), 1000);
+        assert_eq!(dict.len(), 4);
+        let dict_bytes = dict.to_bytes().len();
+        assert_eq!(
+            stats.breakdown,
+            Breakdown::Dictionary {
+                cardinality: 4,
+                dict_bytes
+            }
+        );
+        // Two bits per code
+        assert_eq!(stats.output_bytes, dict_bytes + 250);
+        assert!(stats.ratio() > 20.0);
+    }
+
+    #[test]
+    fn test_xor_controls() {
+        let data = [1.0f64, 1.0, 1.0, 2.0, 3.0, 3.0];
+        let (encoded, stats) = xor_float_encode_with_stats(&data);
+        assert_eq!(encoded, xor_float_encode(&data));
+        assert_eq!((stats.input_bytes, stats.output_bytes), (48, encoded.len()));
+        let Breakdown::Xor(controls) = stats.breakdown else {
+            panic!("expected XOR breakdown");
+        };
+        assert_eq!(controls.identical, 3);
+        assert_eq!(controls.reused_window + controls.new_window, 2);
+
+        let (_, stats) = xor_float_encode_with_stats(&[2.5f32; 10]);
+        assert_eq!(stats.input_bytes, 40);
+        assert_eq!(
+            stats.breakdown,
+            Breakdown::Xor(XorControls {
+                identical: 9,
+                ..XorControls::default()
+            })
+        );
+    }
+}
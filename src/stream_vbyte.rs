@@ -0,0 +1,238 @@
+// Stream VByte: each u32 is stored in 1 to 4 little-endian bytes, with the
+// byte lengths kept in a separate stream of 2 bit control codes (four values
+// per control byte). Splitting the streams means a whole control byte can be
+// decoded at once with a single SSSE3 shuffle.
+//
+// Layout: varint value count | ceil(count / 4) control bytes | data bytes
+
+use crate::{
+    error::CodingError,
+    varint::{decode_u64, encode_u64},
+};
+
+// Total data bytes described by each control byte
+const LENGTHS: [u8; 256] = build_lengths();
+
+const fn build_lengths() -> [u8; 256] {
+    let mut lengths = [0; 256];
+    let mut control = 0;
+    while control < 256 {
+        let mut idx = 0;
+        while idx < 4 {
+            lengths[control] += ((control >> (2 * idx)) & 3) as u8 + 1;
+            idx += 1;
+        }
+        control += 1;
+    }
+    lengths
+}
+
+pub fn stream_vbyte_encode(data: &[u32]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    encode_u64(data.len() as u64, &mut encoded);
+    let control_start = encoded.len();
+    encoded.resize(control_start + data.len().div_ceil(4), 0);
+
+    for (idx, value) in data.iter().enumerate() {
+        let len = byte_len(*value);
+        encoded[control_start + idx / 4] |= (len - 1) << (2 * (idx % 4));
+        encoded.extend_from_slice(&value.to_le_bytes()[..len as usize]);
+    }
+    encoded
+}
+
+// Uses the SSSE3 decoder when the CPU supports it
+pub fn stream_vbyte_decode(data: &[u8]) -> Result<Vec<u32>, CodingError> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            let (len, control, values) = split_streams(data)?;
+            let mut decoded = Vec::with_capacity(len);
+            // Safety: the CPU supports SSSE3 and `split_streams` checked that
+            // `values` holds every byte `control` refers to
+            unsafe { ssse3::decode(len, control, values, &mut decoded) };
+            return Ok(decoded);
+        }
+    }
+    stream_vbyte_decode_scalar(data)
+}
+
+pub fn stream_vbyte_decode_scalar(data: &[u8]) -> Result<Vec<u32>, CodingError> {
+    let (len, control, values) = split_streams(data)?;
+    let mut decoded = Vec::with_capacity(len);
+    decode_scalar(len, control, values, &mut decoded);
+    Ok(decoded)
+}
+
+fn byte_len(value: u32) -> u8 {
+    match value {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    }
+}
+
+// Splits an encoded buffer into the value count, control stream and data
+// stream, checking the data stream is long enough for the controls
+fn split_streams(data: &[u8]) -> Result<(usize, &[u8], &[u8]), CodingError> {
+    let (len, header_len) = decode_u64(data)?;
+    let len = len as usize;
+    let buff = &data[header_len..];
+    let control_len = len.div_ceil(4);
+    if buff.len() < control_len {
+        return Err(CodingError::Truncated);
+    }
+    let (control, values) = buff.split_at(control_len);
+
+    let full_len: usize = control[..len / 4]
+        .iter()
+        .map(|x| LENGTHS[*x as usize] as usize)
+        .sum();
+    let tail_len: usize = (0..len % 4)
+        .map(|idx| ((control[len / 4] >> (2 * idx)) & 3) as usize + 1)
+        .sum();
+    if values.len() < full_len + tail_len {
+        return Err(CodingError::Truncated);
+    }
+    Ok((len, control, values))
+}
+
+fn decode_scalar(len: usize, control: &[u8], values: &[u8], decoded: &mut Vec<u32>) {
+    let mut offset = 0;
+    for idx in decoded.len()..len {
+        let byte_len = ((control[idx / 4] >> (2 * (idx % 4))) & 3) as usize + 1;
+        let mut bytes = [0u8; 4];
+        bytes[..byte_len].copy_from_slice(&values[offset..offset + byte_len]);
+        decoded.push(u32::from_le_bytes(bytes));
+        offset += byte_len;
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod ssse3 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::{decode_scalar, LENGTHS};
+
+    // For each control byte, the `pshufb` mask moving each value's bytes
+    // into its own 4 byte lane, with 0x80 zeroing the unused high bytes
+    static SHUFFLES: [[u8; 16]; 256] = build_shuffles();
+
+    const fn build_shuffles() -> [[u8; 16]; 256] {
+        let mut shuffles = [[0x80; 16]; 256];
+        let mut control = 0;
+        while control < 256 {
+            let mut offset = 0;
+            let mut idx = 0;
+            while idx < 4 {
+                let len = ((control >> (2 * idx)) & 3) + 1;
+                let mut byte = 0;
+                while byte < len {
+                    shuffles[control][4 * idx + byte] = (offset + byte) as u8;
+                    byte += 1;
+                }
+                offset += len;
+                idx += 1;
+            }
+            control += 1;
+        }
+        shuffles
+    }
+
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn decode(len: usize, control: &[u8], values: &[u8], decoded: &mut Vec<u32>) {
+        let mut offset = 0;
+        for control_byte in &control[..len / 4] {
+            // Every 16 byte load has to stay inside `values`, the last few
+            // groups are left to the scalar decoder
+            if offset + 16 > values.len() {
+                break;
+            }
+            let input = _mm_loadu_si128(values.as_ptr().add(offset) as *const __m128i);
+            let shuffle =
+                _mm_loadu_si128(SHUFFLES[*control_byte as usize].as_ptr() as *const __m128i);
+            let unpacked = _mm_shuffle_epi8(input, shuffle);
+            let start = decoded.len();
+            decoded.reserve(4);
+            _mm_storeu_si128(decoded.as_mut_ptr().add(start) as *mut __m128i, unpacked);
+            decoded.set_len(start + 4);
+            offset += LENGTHS[*control_byte as usize] as usize;
+        }
+        decode_scalar(len, control, &values[offset..], decoded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_values() -> Vec<u32> {
+        (0..1001u32)
+            .map(|x| x.wrapping_mul(0x9E3779B9) >> (8 * (x % 4)))
+            .collect()
+    }
+
+    #[test]
+    fn test_encode() {
+        let encoded = stream_vbyte_encode(&[1, 256, 65536, u32::MAX, 7]);
+        let correct = vec![
+            5,
+            0b11_10_01_00,
+            0b00,
+            1,
+            0,
+            1,
+            0,
+            0,
+            1,
+            0xFF,
+            0xFF,
+            0xFF,
+            0xFF,
+            7,
+        ];
+        assert_eq!(encoded, correct);
+    }
+
+    #[test]
+    fn test_round_trip_scalar() {
+        let data = test_values();
+        let encoded = stream_vbyte_encode(&data);
+        assert_eq!(stream_vbyte_decode_scalar(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for len in [0, 1, 3, 4, 5, 17, 1001] {
+            let data = &test_values()[..len];
+            let encoded = stream_vbyte_encode(data);
+            assert_eq!(stream_vbyte_decode(&encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_small_values_one_byte() {
+        let data = vec![3; 64];
+        let encoded = stream_vbyte_encode(&data);
+        assert_eq!(encoded.len(), 1 + 16 + 64);
+        assert_eq!(stream_vbyte_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let encoded = stream_vbyte_encode(&test_values());
+        let truncated = &encoded[..encoded.len() - 1];
+        assert!(matches!(
+            stream_vbyte_decode(truncated),
+            Err(CodingError::Truncated)
+        ));
+        assert!(matches!(
+            stream_vbyte_decode(&[8, 0]),
+            Err(CodingError::Truncated)
+        ));
+    }
+}
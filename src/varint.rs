@@ -0,0 +1,259 @@
+// LEB128 varints: 7 bits of the value per byte, least significant group
+// first, with the high bit set on every byte but the last. Signed values are
+// zigzag mapped first so small negative numbers stay short.
+//
+// Decoding is strict: a value padded with redundant zero groups is rejected
+// as overlong, so every value has exactly one valid encoding.
+
+use std::io::{self, Read, Write};
+
+use crate::error::CodingError;
+
+// A u64 needs at most 10 groups of 7 bits
+pub const MAX_LEN: usize = 10;
+
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn zigzag_decode(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+pub fn encode_u64(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub fn encode_i64(value: i64, out: &mut Vec<u8>) {
+    encode_u64(zigzag_encode(value), out);
+}
+
+// Bytes `encode_u64` uses for `value`
+pub fn encoded_len(value: u64) -> usize {
+    (64 - value.leading_zeros() as usize).div_ceil(7).max(1)
+}
+
+// Returns the value and the number of bytes it used
+pub fn decode_u64(data: &[u8]) -> Result<(u64, usize), CodingError> {
+    let mut value = 0;
+    for (idx, byte) in data.iter().enumerate() {
+        if accumulate(&mut value, *byte, idx)? {
+            return Ok((value, idx + 1));
+        }
+    }
+    Err(CodingError::Truncated)
+}
+
+pub fn decode_i64(data: &[u8]) -> Result<(i64, usize), CodingError> {
+    let (value, len) = decode_u64(data)?;
+    Ok((zigzag_decode(value), len))
+}
+
+pub fn encode_u64_slice(data: &[u64]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len());
+    for value in data {
+        encode_u64(*value, &mut encoded);
+    }
+    encoded
+}
+
+pub fn decode_u64_slice(data: &[u8]) -> Result<Vec<u64>, CodingError> {
+    let mut decoded = Vec::new();
+    let mut buff = data;
+    while !buff.is_empty() {
+        let (value, len) = decode_u64(buff)?;
+        decoded.push(value);
+        buff = &buff[len..];
+    }
+    Ok(decoded)
+}
+
+pub fn encode_i64_slice(data: &[i64]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len());
+    for value in data {
+        encode_i64(*value, &mut encoded);
+    }
+    encoded
+}
+
+pub fn decode_i64_slice(data: &[u8]) -> Result<Vec<i64>, CodingError> {
+    Ok(decode_u64_slice(data)?
+        .into_iter()
+        .map(zigzag_decode)
+        .collect())
+}
+
+// Returns the number of bytes written
+pub fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<usize> {
+    let mut buff = Vec::with_capacity(MAX_LEN);
+    encode_u64(value, &mut buff);
+    writer.write_all(&buff)?;
+    Ok(buff.len())
+}
+
+pub fn write_i64<W: Write>(writer: &mut W, value: i64) -> io::Result<usize> {
+    write_u64(writer, zigzag_encode(value))
+}
+
+// Reads one byte at a time so nothing past the varint is consumed
+pub fn read_u64<R: Read>(reader: &mut R) -> Result<u64, CodingError> {
+    let mut value = 0;
+    let mut byte = [0u8];
+    for idx in 0..MAX_LEN {
+        if let Err(err) = reader.read_exact(&mut byte) {
+            return Err(match err.kind() {
+                io::ErrorKind::UnexpectedEof => CodingError::Truncated,
+                _ => CodingError::Io(err),
+            });
+        }
+        if accumulate(&mut value, byte[0], idx)? {
+            return Ok(value);
+        }
+    }
+    Err(CodingError::Overflow)
+}
+
+pub fn read_i64<R: Read>(reader: &mut R) -> Result<i64, CodingError> {
+    Ok(zigzag_decode(read_u64(reader)?))
+}
+
+// Cursor style helpers for parsing headers: read from the front of `data`
+// and advance it past what was read
+pub(crate) fn take_u64(data: &mut &[u8]) -> Result<u64, CodingError> {
+    let (value, len) = decode_u64(data)?;
+    *data = &data[len..];
+    Ok(value)
+}
+
+pub(crate) fn take_i64(data: &mut &[u8]) -> Result<i64, CodingError> {
+    Ok(zigzag_decode(take_u64(data)?))
+}
+
+pub(crate) fn take_bytes<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], CodingError> {
+    if data.len() < len {
+        return Err(CodingError::Truncated);
+    }
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    Ok(bytes)
+}
+
+pub(crate) fn take_u8(data: &mut &[u8]) -> Result<u8, CodingError> {
+    Ok(take_bytes(data, 1)?[0])
+}
+
+// Adds the `idx`th byte of a varint to `value`, returning whether it was the
+// last one
+fn accumulate(value: &mut u64, byte: u8, idx: usize) -> Result<bool, CodingError> {
+    let group = (byte & 0x7F) as u64;
+    if idx == MAX_LEN - 1 && byte > 1 {
+        // Only a single bit of the 64 is left for the tenth byte
+        return Err(CodingError::Overflow);
+    }
+    *value |= group << (7 * idx);
+    if byte & 0x80 != 0 {
+        return Ok(false);
+    }
+    if byte == 0 && idx > 0 {
+        return Err(CodingError::Overlong);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_u64() {
+        let mut encoded = Vec::new();
+        encode_u64(0, &mut encoded);
+        encode_u64(127, &mut encoded);
+        encode_u64(300, &mut encoded);
+        assert_eq!(encoded, vec![0x00, 0x7F, 0xAC, 0x02]);
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        for value in [0, 1, 127, 128, 16383, 16384, u64::MAX] {
+            let mut out = Vec::new();
+            encode_u64(value, &mut out);
+            assert_eq!(encoded_len(value), out.len());
+        }
+    }
+
+    #[test]
+    fn test_zigzag() {
+        assert_eq!(zigzag_encode(0), 0);
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+        assert_eq!(zigzag_encode(i64::MIN), u64::MAX);
+        for value in [0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+            assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+        }
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let unsigned = vec![0, 1, 127, 128, 16384, u32::MAX as u64, u64::MAX];
+        let encoded = encode_u64_slice(&unsigned);
+        assert_eq!(decode_u64_slice(&encoded).unwrap(), unsigned);
+
+        let signed = vec![0, -1, 1, -64, 64, i64::MIN, i64::MAX];
+        let encoded = encode_i64_slice(&signed);
+        assert_eq!(decode_i64_slice(&encoded).unwrap(), signed);
+    }
+
+    #[test]
+    fn test_max_len() {
+        let mut encoded = Vec::new();
+        encode_u64(u64::MAX, &mut encoded);
+        assert_eq!(encoded.len(), MAX_LEN);
+        assert_eq!(decode_u64(&encoded).unwrap(), (u64::MAX, MAX_LEN));
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert!(matches!(decode_u64(&[]), Err(CodingError::Truncated)));
+        assert!(matches!(
+            decode_u64(&[0xAC, 0x82]),
+            Err(CodingError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_overlong() {
+        assert!(matches!(
+            decode_u64(&[0x80, 0x00]),
+            Err(CodingError::Overlong)
+        ));
+        assert!(matches!(
+            decode_u64(&[0xAC, 0x82, 0x00]),
+            Err(CodingError::Overlong)
+        ));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut too_big = vec![0xFF; MAX_LEN - 1];
+        too_big.push(0x02);
+        assert!(matches!(decode_u64(&too_big), Err(CodingError::Overflow)));
+        let too_long = vec![0x80; MAX_LEN + 1];
+        assert!(matches!(decode_u64(&too_long), Err(CodingError::Overflow)));
+    }
+
+    #[test]
+    fn test_io_round_trip() {
+        let mut buff = Vec::new();
+        assert_eq!(write_u64(&mut buff, 300).unwrap(), 2);
+        write_i64(&mut buff, -3).unwrap();
+        let mut reader = buff.as_slice();
+        assert_eq!(read_u64(&mut reader).unwrap(), 300);
+        assert_eq!(read_i64(&mut reader).unwrap(), -3);
+        assert!(matches!(read_u64(&mut reader), Err(CodingError::Truncated)));
+    }
+}